@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sqframe::{square_bytes, SqframeOptions};
+
+// Arbitrary bytes must make square_bytes return an error, never panic
+fuzz_target!(|data: &[u8]| {
+    _ = square_bytes(data, &SqframeOptions::default());
+});
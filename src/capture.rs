@@ -0,0 +1,25 @@
+use crate::SqframeError;
+use image::DynamicImage;
+use screenshots::Screen;
+use std::{fmt::Display, io};
+
+/// Wraps a failure of the screenshot backend in an I/O error
+fn capture_error(e: impl Display) -> SqframeError {
+    SqframeError::Io {
+        context: String::from("could not capture the screen"),
+        source: io::Error::other(e.to_string()),
+    }
+}
+
+/// Captures the primary screen, or the first screen if none is marked as primary
+pub fn screen() -> Result<DynamicImage, SqframeError> {
+    let screens = Screen::all().map_err(capture_error)?;
+    let screen = screens
+        .iter()
+        .find(|screen| screen.display_info.is_primary)
+        .or_else(|| screens.first())
+        .ok_or_else(|| capture_error("no screen was found"))?;
+    let image = screen.capture().map_err(capture_error)?;
+    progress!("Captured screen ({}x{})", image.width(), image.height());
+    Ok(DynamicImage::ImageRgba8(image))
+}
@@ -0,0 +1,253 @@
+use image::{DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+
+/// Converts CIE XYZ (D50, the ICC profile connection space) to linear sRGB, Bradford-adapted to D65
+const XYZ_D50_TO_LINEAR_SRGB: [[f32; 3]; 3] = [
+    [3.133856, -1.6168667, -0.4906146],
+    [-0.9787684, 1.9161415, 0.0334540],
+    [0.0719453, -0.2289914, 1.4052427],
+];
+/// Number of entries in the table used to encode linear values as sRGB
+const ENCODE_STEPS: usize = 4096;
+
+/// A tone reproduction curve, mapping an encoded channel value to a linear one (both in 0..=1)
+enum Curve {
+    Gamma(f32),
+    Table(Vec<f32>),
+    /// ICC parametric curve: gamma followed by the a, b, c, d, e, f parameters of its function type
+    Parametric(f32, [f32; 6]),
+}
+
+impl Curve {
+    fn linearize(&self, x: f32) -> f32 {
+        match self {
+            Curve::Gamma(gamma) => x.powf(*gamma),
+            Curve::Table(table) => {
+                let pos = x * (table.len() - 1) as f32;
+                let i = (pos.floor() as usize).min(table.len() - 2);
+                table[i] + (table[i + 1] - table[i]) * (pos - i as f32)
+            }
+            Curve::Parametric(g, [a, b, c, d, e, f]) => {
+                if x >= *d {
+                    (a * x + b).max(0.).powf(*g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        }
+    }
+}
+
+fn u16_at(bytes: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(pos..pos + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(pos..pos + 4)?.try_into().ok()?,
+    ))
+}
+
+fn s15_fixed16_at(bytes: &[u8], pos: usize) -> Option<f32> {
+    Some(u32_at(bytes, pos)? as i32 as f32 / 65536.)
+}
+
+/// Returns the data of the tag with signature `sig` in an ICC profile
+fn tag<'a>(icc: &'a [u8], sig: &[u8]) -> Option<&'a [u8]> {
+    let count = u32_at(icc, 128)? as usize;
+    (0..count).map(|i| 132 + i * 12).find_map(|entry| {
+        if icc.get(entry..entry + 4)? != sig {
+            return None;
+        }
+        let offset = u32_at(icc, entry + 4)? as usize;
+        let size = u32_at(icc, entry + 8)? as usize;
+        icc.get(offset..offset.checked_add(size)?)
+    })
+}
+
+fn read_xyz(icc: &[u8], sig: &[u8]) -> Option<[f32; 3]> {
+    let data = tag(icc, sig)?;
+    if data.get(..4)? != b"XYZ " {
+        return None;
+    }
+    Some([
+        s15_fixed16_at(data, 8)?,
+        s15_fixed16_at(data, 12)?,
+        s15_fixed16_at(data, 16)?,
+    ])
+}
+
+fn read_curve(icc: &[u8], sig: &[u8]) -> Option<Curve> {
+    let data = tag(icc, sig)?;
+    match data.get(..4)? {
+        b"curv" => match u32_at(data, 8)? as usize {
+            0 => Some(Curve::Gamma(1.)),
+            1 => Some(Curve::Gamma(u16_at(data, 12)? as f32 / 256.)),
+            count => (0..count)
+                .map(|i| Some(u16_at(data, 12 + i * 2)? as f32 / 65535.))
+                .collect::<Option<_>>()
+                .map(Curve::Table),
+        },
+        b"para" => {
+            let param = |i: usize| s15_fixed16_at(data, 12 + i * 4);
+            let g = param(0)?;
+            // expand every function type to the general form y = (ax + b)^g + e for x >= d, else cx + f
+            let params = match u16_at(data, 8)? {
+                0 => [1., 0., 0., 0., 0., 0.],
+                1 => {
+                    let (a, b) = (param(1)?, param(2)?);
+                    [a, b, 0., -b / a, 0., 0.]
+                }
+                2 => {
+                    let (a, b, c) = (param(1)?, param(2)?, param(3)?);
+                    [a, b, 0., -b / a, c, c]
+                }
+                3 => [param(1)?, param(2)?, param(3)?, param(4)?, 0., 0.],
+                4 => [
+                    param(1)?,
+                    param(2)?,
+                    param(3)?,
+                    param(4)?,
+                    param(5)?,
+                    param(6)?,
+                ],
+                _ => return None,
+            };
+            Some(Curve::Parametric(g, params))
+        }
+        _ => None,
+    }
+}
+
+fn decode_srgb(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn encode_srgb(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Returns the image's pixels as linear-light values (0..=1), row by row
+pub fn srgb_to_linear(image: &RgbImage) -> Vec<[f32; 3]> {
+    let table: Vec<f32> = (0..=255)
+        .map(|channel| decode_srgb(channel as f32 / 255.))
+        .collect();
+    image
+        .pixels()
+        .map(|pixel| pixel.0.map(|channel| table[channel as usize]))
+        .collect()
+}
+
+/// Returns a `width`×`height` sRGB image of linear-light values (0..=1) given row by row
+pub fn linear_to_srgb(pixels: &[[f32; 3]], width: u32) -> RgbImage {
+    let height = pixels.len() as u32 / width.max(1);
+    RgbImage::from_fn(width, height, |x, y| {
+        let linear = pixels[(y * width + x) as usize];
+        Rgb(linear.map(|channel| (encode_srgb(channel.clamp(0., 1.)) * 255.).round() as u8))
+    })
+}
+
+/// Returns the relative luminance (from 0 for black to 1 for white) of an sRGB color, as defined by WCAG
+pub fn relative_luminance(color: [u8; 3]) -> f32 {
+    let [r, g, b] = color.map(|channel| decode_srgb(channel as f32 / 255.));
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Returns the WCAG contrast ratio (from 1 for equal luminances to 21 for black on white) between two
+/// relative luminances
+pub fn contrast_ratio(a: f32, b: f32) -> f32 {
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Returns the image with its pixels converted from the RGB matrix/TRC profile `icc` to sRGB
+pub fn to_srgb(image: &DynamicImage, icc: &[u8]) -> Result<DynamicImage, String> {
+    if icc.get(16..20) != Some(b"RGB ") {
+        return Err(String::from("the ICC profile is not an RGB profile"));
+    }
+    let colorants = [b"rXYZ", b"gXYZ", b"bXYZ"]
+        .map(|sig| read_xyz(icc, sig))
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or("the ICC profile has no RGB colorants (only matrix/TRC profiles are supported)")?;
+    let curves = [b"rTRC", b"gTRC", b"bTRC"]
+        .map(|sig| read_curve(icc, sig))
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or("the ICC profile has unsupported tone curves")?;
+    // rows of the combined profile RGB -> XYZ (D50) -> linear sRGB matrix
+    let mut matrix = [[0f32; 3]; 3];
+    for (row, out) in matrix.iter_mut().enumerate() {
+        for (channel, colorant) in colorants.iter().enumerate() {
+            out[channel] = (0..3)
+                .map(|i| XYZ_D50_TO_LINEAR_SRGB[row][i] * colorant[i])
+                .sum();
+        }
+    }
+    let linear: Vec<[f32; 256]> = curves
+        .iter()
+        .map(|curve| std::array::from_fn(|value| curve.linearize(value as f32 / 255.)))
+        .collect();
+    let encoded: Vec<u8> = (0..ENCODE_STEPS)
+        .map(|step| (encode_srgb(step as f32 / (ENCODE_STEPS - 1) as f32) * 255.).round() as u8)
+        .collect();
+    let source = image.to_rgba8();
+    let converted = RgbaImage::from_fn(source.width(), source.height(), |x, y| {
+        let Rgba([r, g, b, a]) = *source.get_pixel(x, y);
+        let input = [
+            linear[0][r as usize],
+            linear[1][g as usize],
+            linear[2][b as usize],
+        ];
+        let mut output = [0u8; 4];
+        for (channel, row) in matrix.iter().enumerate() {
+            let value: f32 = (0..3).map(|i| row[i] * input[i]).sum();
+            let step = (value.clamp(0., 1.) * (ENCODE_STEPS - 1) as f32).round() as usize;
+            output[channel] = encoded[step];
+        }
+        output[3] = a;
+        Rgba(output)
+    });
+    Ok(if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(converted)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(converted).into_rgb8())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_luminance_follows_wcag() {
+        assert_eq!(relative_luminance([0, 0, 0]), 0.);
+        assert!((relative_luminance([255, 255, 255]) - 1.).abs() < 1e-6);
+        // green contributes most, blue least
+        let [red, green, blue] = [[255, 0, 0], [0, 255, 0], [0, 0, 255]].map(relative_luminance);
+        assert!((red - 0.2126).abs() < 1e-4);
+        assert!((green - 0.7152).abs() < 1e-4);
+        assert!((blue - 0.0722).abs() < 1e-4);
+        // mid gray is far darker than half the light
+        assert!((relative_luminance([128, 128, 128]) - 0.2159).abs() < 1e-3);
+    }
+
+    #[test]
+    fn contrast_ratio_spans_1_to_21() {
+        let (black, white) = (relative_luminance([0; 3]), relative_luminance([255; 3]));
+        assert!((contrast_ratio(black, white) - 21.).abs() < 1e-4);
+        assert_eq!(contrast_ratio(white, black), contrast_ratio(black, white));
+        assert_eq!(contrast_ratio(0.3, 0.3), 1.);
+        // #777777 on white is the classic example just below the 4.5:1 AA threshold
+        let gray = relative_luminance([0x77; 3]);
+        assert!((contrast_ratio(gray, white) - 4.48).abs() < 0.01);
+    }
+}
@@ -0,0 +1,86 @@
+use clap::ValueEnum;
+use std::env;
+
+/// Language of the prompts that ask before overwriting something
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Lang {
+    /// English
+    En,
+    /// German
+    De,
+    /// Spanish
+    Es,
+    /// French
+    Fr,
+}
+
+impl Lang {
+    /// Returns the language of the first of `LC_ALL`, `LC_MESSAGES` and `LANG` that is set (e.g. `de_DE.UTF-8`),
+    /// falling back to English for unset variables and unsupported languages
+    pub fn from_env() -> Lang {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        let code = locale.split(['_', '.', '@']).next().unwrap_or_default();
+        Lang::from_str(code, true).unwrap_or(Lang::En)
+    }
+
+    /// Asks whether to replace the existing file at `path`
+    pub fn replace_file(self, path: &str) -> String {
+        match self {
+            Lang::En => format!("{path} is an existing file. replace?"),
+            Lang::De => format!("{path} existiert bereits. Ersetzen?"),
+            Lang::Es => format!("{path} ya existe. ¿Reemplazar?"),
+            Lang::Fr => format!("{path} existe déjà. Remplacer ?"),
+        }
+    }
+
+    /// Asks whether to overwrite the clipboard's content
+    pub fn replace_clipboard(self) -> &'static str {
+        match self {
+            Lang::En => "Overwrite clipboard content with edited image?",
+            Lang::De => "Inhalt der Zwischenablage durch das bearbeitete Bild ersetzen?",
+            Lang::Es => "¿Reemplazar el contenido del portapapeles por la imagen editada?",
+            Lang::Fr => "Remplacer le contenu du presse-papiers par l'image modifiée ?",
+        }
+    }
+
+    /// Returns the answer hint shown after a prompt, with the default answer capitalized
+    pub fn hint(self, default: bool) -> &'static str {
+        match (self, default) {
+            (Lang::En, true) => "[Y/n]",
+            (Lang::En, false) => "[y/N]",
+            (Lang::De, true) => "[J/n]",
+            (Lang::De, false) => "[j/N]",
+            (Lang::Es, true) => "[S/n]",
+            (Lang::Es, false) => "[s/N]",
+            (Lang::Fr, true) => "[O/n]",
+            (Lang::Fr, false) => "[o/N]",
+        }
+    }
+
+    /// Returns whether a (trimmed, lowercase) answer means yes; English answers are understood in every
+    /// language
+    pub fn is_yes(self, answer: &str) -> bool {
+        let localized: &[&str] = match self {
+            Lang::En => &[],
+            Lang::De => &["j", "ja"],
+            Lang::Es => &["s", "si", "sí"],
+            Lang::Fr => &["o", "oui"],
+        };
+        ["y", "yes"].contains(&answer) || localized.contains(&answer)
+    }
+
+    /// Returns whether a (trimmed, lowercase) answer means no; English answers are understood in every
+    /// language
+    pub fn is_no(self, answer: &str) -> bool {
+        let localized: &[&str] = match self {
+            Lang::En | Lang::Es => &[],
+            Lang::De => &["nein"],
+            Lang::Fr => &["non"],
+        };
+        ["n", "no"].contains(&answer) || localized.contains(&answer)
+    }
+}
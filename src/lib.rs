@@ -1,289 +1,5092 @@
+/// Prints a progress message like `println!`, but to stderr while `PROGRESS_TO_STDERR` is set, so that it does
+/// not mix with output printed on stdout
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::PROGRESS_TO_STDERR.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+#[cfg(feature = "capture")]
+mod capture;
+mod color;
+mod font;
+mod lang;
+mod manifest;
+mod metadata;
+#[cfg(feature = "server")]
+mod server;
+mod sidecar;
+#[cfg(feature = "video")]
+mod video;
+
 use arboard::{Clipboard, ImageData};
-use clap::Parser;
-use colored::Colorize;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::{Parser, Subcommand, ValueEnum};
+use color_quant::NeuQuant;
 use fastblur::gaussian_blur;
 use image::{
-    imageops::FilterType, io::Reader as ImageReader, DynamicImage, GenericImageView, ImageBuffer,
-    Pixel, Rgb, RgbImage,
+    codecs::{
+        gif::GifDecoder,
+        jpeg::{JpegEncoder, PixelDensity},
+    },
+    error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind},
+    imageops::{self, ColorMap, FilterType},
+    io::Reader as ImageReader,
+    AnimationDecoder, DynamicImage, GenericImage, GenericImageView, ImageBuffer, ImageError,
+    ImageFormat, Pixel, Rgb, RgbImage, Rgba, RgbaImage,
 };
+use lang::Lang;
+use manifest::Status;
+use memmap2::Mmap;
+use metadata::Metadata;
+use rayon::{prelude::*, ThreadPoolBuilder};
+use sidecar::Sidecar;
 use std::{
+    array,
     borrow::Cow,
-    cmp::{max, min},
-    env, fs, io,
-    io::Write,
-    path::{Path, PathBuf},
+    cmp::{max, min, Reverse},
+    collections::{HashMap, HashSet},
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, Write},
+    path::{self, Path, PathBuf},
     process,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use thiserror::Error;
+use zip::{result::ZipError, write::FileOptions, ZipArchive, ZipWriter};
 
-/// A tool to create a square frame with a blurred background for any image, to match the aspect ratio 1:1
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-pub struct Args {
-    /// Input file path, defaults to clipboard
-    #[arg(short, long)]
-    input_path: Option<String>,
+/// Whether progress messages and prompts go to stderr, as they do when stdout carries the output itself
+static PROGRESS_TO_STDERR: AtomicBool = AtomicBool::new(false);
 
-    /// Output file path, defaults to clipboard
-    #[arg(short, long)]
-    output_path: Option<String>,
+/// Blur radius used for the background fill when none is specified
+const DEFAULT_BLUR: f32 = 16.;
+/// Largest blur radius accepted, far beyond the point where a background has blurred to a near-uniform color;
+/// much larger radii overflow fastblur's box sizes
+const MAX_BLUR_RADIUS: f32 = 1000.;
+/// Largest number of pixels the output square or the upscaled background may have before framing is refused
+const MAX_BACKGROUND_PIXELS: u64 = 1 << 28;
+/// Largest side of the background while it is built and blurred with `--fast`
+const FAST_BACKGROUND_SIDE: u32 = 256;
+/// Largest percentage accepted by `--scale`
+const MAX_SCALE: f32 = 1000.;
+/// Largest raw RGBA buffer copied as a bitmap before a compressed preview is copied instead
+const MAX_CLIPBOARD_BITMAP_BYTES: u64 = 256 << 20;
+/// Largest number of pixels sampled when extracting an image's dominant colors
+const MAX_PALETTE_SAMPLES: usize = 4096;
+/// Number of k-means refinements when extracting an image's dominant colors
+const PALETTE_ITERATIONS: usize = 10;
+/// How often `--watch` checks its directory for new images
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// Largest mean per-channel difference between neighbouring pixels of a line for it to count as part of a
+/// uniform or blurred bar
+const BAR_ROUGHNESS: f32 = 0.75;
+
+/// An error encountered while reading, framing or writing an image
+#[derive(Debug, Error)]
+pub enum SqframeError {
+    /// A file (or stdin) could not be read or written
+    #[error("{context}: {source}")]
+    Io {
+        /// What was being done when the error occurred
+        context: String,
+        source: io::Error,
+    },
+    /// The input could not be decoded
+    #[error("could not decode image: {0}")]
+    Decode(#[source] ImageError),
+    /// The output could not be encoded
+    #[error("could not encode image: {0}")]
+    Encode(#[source] ImageError),
+    /// The clipboard could not be read or written
+    #[error("{0}")]
+    Clipboard(String),
+    /// The arguments or options cannot be applied to the input or the requested output
+    #[error("invalid arguments: {0}")]
+    InvalidArgs(String),
+    /// The image's dimensions cannot be framed
+    #[error("invalid dimensions: {0}")]
+    Dimension(String),
+    /// The image contrasts too little with the background around it (with `--strict`)
+    #[error("{0}")]
+    LowContrast(String),
 }
 
-/// Returns a vector containing arrays of length 3 (R, G, B) corresponding to the pixels in the image
-fn get_colors(image: &DynamicImage) -> Vec<[u8; 3]> {
-    let pixels = image.pixels();
-    let mut colors: Vec<[u8; 3]> = Vec::new();
-    for (_, _, pixel) in pixels {
-        colors.push(pixel.to_rgb().0);
+impl SqframeError {
+    /// Returns the process exit status for the error's category, as listed in `--help`
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SqframeError::InvalidArgs(_) => 2,
+            SqframeError::Decode(_) => 3,
+            SqframeError::Io { .. } => 4,
+            SqframeError::Clipboard(_) => 5,
+            SqframeError::Encode(_) => 6,
+            SqframeError::Dimension(_) => 7,
+            SqframeError::LowContrast(_) => 8,
+        }
     }
-    colors
+
+    /// Returns a closure wrapping an `io::Error` with `context`, for use with `map_err`
+    fn io(context: impl Into<String>) -> impl FnOnce(io::Error) -> SqframeError {
+        let context = context.into();
+        move |source| SqframeError::Io { context, source }
+    }
+}
+
+/// A downscaled copy of the final image to save alongside the output
+#[derive(Clone, Debug)]
+struct Thumbnail {
+    size: u32,
+    path: PathBuf,
+    format: ImageFormat,
 }
 
-/// Returns a vector containing arrays of length 4 (R, G, B, A) corresponding to the pixels in the image
-fn get_colors_alpha(image: &DynamicImage) -> Vec<[u8; 4]> {
-    let pixels = image.pixels();
-    let mut colors: Vec<[u8; 4]> = Vec::new();
-    for (_, _, pixel) in pixels {
-        colors.push(pixel.0);
+/// Parses a thumbnail from `<size>:<path>`
+fn parse_thumbnail(value: &str) -> Result<Thumbnail, String> {
+    let (size, path) = value
+        .split_once(':')
+        .ok_or("expected <size>:<path>, e.g. 256:thumb.png")?;
+    let size = size
+        .parse()
+        .map_err(|e| format!("invalid thumbnail size {size:?}: {e}"))?;
+    if size == 0 {
+        return Err(String::from("thumbnail size must be greater than 0"));
     }
-    colors
+    let format = ImageFormat::from_path(path)
+        .map_err(|e| format!("could not determine the thumbnail format of {path:?}: {e}"))?;
+    Ok(Thumbnail {
+        size,
+        path: PathBuf::from(path),
+        format,
+    })
 }
 
-/// Returns a blurred (Gaussian blur) copy of the image, with `intensity` being the blur radius
-fn blur(image: &DynamicImage, intensity: f32) -> DynamicImage {
-    let (width, height) = (image.width(), image.height());
-    let mut colors = get_colors(&image);
-    gaussian_blur(&mut colors, width as usize, height as usize, intensity);
-    let mut blurred_image_buffer = RgbImage::new(width, height);
-    let mut pixel_index = 0usize;
-    for y in 0..height {
-        for x in 0..width {
-            blurred_image_buffer.put_pixel(x, y, Rgb(colors[pixel_index]));
-            pixel_index += 1;
-        }
+/// Parses the path of the `--compare` image, taking its format from the extension
+fn parse_compare(value: &str) -> Result<(PathBuf, ImageFormat), String> {
+    let format = ImageFormat::from_path(value)
+        .map_err(|e| format!("could not determine the comparison format of {value:?}: {e}"))?;
+    Ok((PathBuf::from(value), format))
+}
+
+/// Parses an image format from its name or a file extension, e.g. "png" or "jpg"
+fn parse_input_format(value: &str) -> Result<ImageFormat, String> {
+    ImageFormat::from_extension(value.to_lowercase())
+        .ok_or(format!("unknown image format {value:?}"))
+}
+
+/// Parses a percentage for `--scale`, rejecting 0 and anything above `MAX_SCALE`
+fn parse_scale(value: &str) -> Result<f32, String> {
+    let percent: f32 = value
+        .parse()
+        .map_err(|e| format!("invalid percentage {value:?}: {e}"))?;
+    if !(percent > 0. && percent <= MAX_SCALE) {
+        return Err(format!(
+            "scale must be greater than 0 and at most {MAX_SCALE}"
+        ));
     }
-    DynamicImage::ImageRgb8(blurred_image_buffer)
+    Ok(percent)
 }
 
-/// Returns an image with `fg` overlaid on `bg`, assuming that `fg` can fit into `bg`
-fn overlay(bg: &DynamicImage, fg: &DynamicImage) -> DynamicImage {
-    let (bg_width, bg_height) = (bg.width(), bg.height());
-    let x_rng = ((bg_width - fg.width()) / 2)..((bg_width + fg.width()) / 2);
-    let y_rng = ((bg_height - fg.height()) / 2)..((bg_height + fg.height()) / 2);
-    let mut final_image = RgbImage::new(bg_width, bg_height);
-    let mut orig_pixels = fg.pixels();
-    for y in 0..bg_height {
-        for x in 0..bg_width {
-            if x_rng.contains(&x) && y_rng.contains(&y) {
-                match orig_pixels.next() {
-                    Some(px) => final_image.put_pixel(x, y, px.2.to_rgb()),
-                    _ => {}
-                }
-                continue;
-            }
-            final_image.put_pixel(x, y, bg.get_pixel(x, y).to_rgb());
-        }
+/// Parses a positive number of seconds for `--timeout`
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|e| format!("invalid number of seconds {value:?}: {e}"))?;
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(timeout) if !timeout.is_zero() => Ok(timeout),
+        _ => Err(String::from("timeout must be a positive number of seconds")),
     }
-    DynamicImage::ImageRgb8(final_image)
 }
 
-/// Returns a hyphen (`"-"`) followed by the current timestamp in milliseconds if successful, otherwise an empty string
-fn get_timestamp_suffix() -> String {
-    match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(duration) => return format!("-{}", duration.as_millis()),
-        Err(_) => return String::from(""),
+/// Clockwise rotation applied to the input
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Rotation {
+    #[value(name = "0")]
+    None,
+    #[value(name = "90")]
+    Quarter,
+    #[value(name = "180")]
+    Half,
+    #[value(name = "270")]
+    ThreeQuarters,
+}
+
+/// Mirroring applied to the input
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Flip {
+    /// Left to right
+    Horizontal,
+    /// Top to bottom
+    Vertical,
+    /// Both ways (the same as rotating by 180°)
+    Both,
+}
+
+impl Flip {
+    /// Returns the image mirrored this way
+    fn apply(self, image: &DynamicImage) -> DynamicImage {
+        match self {
+            Flip::Horizontal => image.fliph(),
+            Flip::Vertical => image.flipv(),
+            Flip::Both => image.fliph().flipv(),
+        }
     }
 }
 
-enum ConfirmResult {
-    Continue,
-    Stop,
-    IOError(io::Error),
+/// Bits per channel of a PNG output
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum BitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
 }
 
-/// Prompts the user with a message, expecting "yes", or "no" and returns a `ConfirmResult`
-fn confirm(msg: String) -> ConfirmResult {
-    let mut stdout = io::stdout();
-    let stdin = io::stdin();
-    let mut resp = String::new();
-    loop {
-        resp.clear();
-        print!("{msg}");
-        _ = stdout.flush();
-        match stdin.read_line(&mut resp) {
-            Ok(_) => {}
-            Err(e) => return ConfirmResult::IOError(e),
-        };
-        resp = resp.trim().to_lowercase();
-        if [String::from("y"), String::from("yes")].contains(&resp) {
-            return ConfirmResult::Continue;
+/// A named combination of output settings, each applied where the output's format supports it
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Preset {
+    /// 8-bit, JPEG quality 80, 72 DPI, without metadata
+    Web,
+    /// JPEG quality 95, 300 DPI, keeping the input's metadata and ICC profile
+    Print,
+    /// 16-bit PNG, JPEG quality 100, keeping the input's metadata and ICC profile
+    Archive,
+}
+
+impl Preset {
+    fn quality(self) -> u8 {
+        match self {
+            Preset::Web => 80,
+            Preset::Print => 95,
+            Preset::Archive => 100,
         }
-        if [String::from("n"), String::from("no")].contains(&resp) {
-            return ConfirmResult::Stop;
+    }
+
+    fn dpi(self) -> Option<u16> {
+        match self {
+            Preset::Web => Some(72),
+            Preset::Print => Some(300),
+            Preset::Archive => None,
+        }
+    }
+
+    fn bit_depth(self) -> Option<BitDepth> {
+        match self {
+            Preset::Web => Some(BitDepth::Eight),
+            Preset::Print => None,
+            Preset::Archive => Some(BitDepth::Sixteen),
+        }
+    }
+
+    fn keeps_metadata(self) -> bool {
+        match self {
+            Preset::Web => false,
+            Preset::Print | Preset::Archive => true,
         }
     }
 }
 
-fn raise(msg: &str) -> ! {
-    eprintln!("ERROR: {}", msg.bold().red());
-    process::exit(1)
+/// Color channels of the output
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Channels {
+    /// Opaque color
+    Rgb,
+    /// Color with transparency (needs PNG or TIFF output)
+    Rgba,
 }
 
-fn open_image_from_path(input_path: &str) -> DynamicImage {
-    match ImageReader::open(input_path) {
-        Ok(opened) => {
-            println!("Opened image from {input_path:?}");
-            match opened.decode() {
-                Ok(img) => {
-                    println!("Decoded image");
-                    img
-                }
-                Err(e) => raise(&format!("Could not decode image: {e:?}")),
-            }
+/// How the image is made square
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Mode {
+    /// Place the image on a square background (see '--background')
+    Frame,
+    /// Crop the image's longer side to a centered square, with no background
+    Crop,
+}
+
+/// What fills the square around the image
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Background {
+    /// A blurred, upscaled copy of the image
+    Blur,
+    /// Full transparency (needs PNG or TIFF output)
+    None,
+    /// A smooth blend of the image's dominant colors (see '--palette-size')
+    Palette,
+    /// The image's average color, much faster than a blur for large images
+    Average,
+}
+
+/// A corner of the output image
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// How the side of the output square is chosen from the image's dimensions
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SquareSize {
+    /// The larger dimension, upscaling the background to fill the square
+    Max,
+    /// The smaller dimension, cropping the image's longer side to fit
+    Min,
+    /// The average of both dimensions, cropping the image's longer side to fit
+    Avg,
+}
+
+impl SquareSize {
+    /// Returns the side of the square for a `width`×`height` image
+    fn side(self, width: u32, height: u32) -> u32 {
+        match self {
+            SquareSize::Max => max(width, height),
+            SquareSize::Min => min(width, height),
+            SquareSize::Avg => ((width as u64 + height as u64) / 2) as u32,
         }
-        Err(e) => raise(&format!("Could not open image: {e:?}")),
     }
 }
 
-fn open_image_from_clipboard() -> DynamicImage {
-    match Clipboard::new() {
-        Ok(mut clipboard) => {
-            println!("Accessed clipboard");
-            match clipboard.get_image() {
-                Ok(img) => {
-                    println!("Read clipboard image");
-                    match ImageBuffer::from_raw(
-                        img.width.try_into().unwrap(),
-                        img.height.try_into().unwrap(),
-                        img.bytes.into_owned(),
-                    ) {
-                        Some(img) => {
-                            println!("Constructed clipboard image");
-                            DynamicImage::ImageRgba8(img)
-                        }
-                        None => raise("Could not construct clipboard image"),
-                    }
-                }
-                Err(e) => raise(&format!("Could not read clipboard image: {e:?}")),
-            }
-        }
-        Err(e) => raise(&format!("Error accessing clipboard: {e:?}")),
+/// A soft glow drawn on the background around the edges of the image
+#[derive(Clone, Copy, Debug)]
+pub struct Glow {
+    /// Blur radius of the glow, in pixels
+    pub radius: f32,
+    pub color: [u8; 3],
+}
+
+/// A region of the input, in pixels, that is kept centered in the square as far as possible
+#[derive(Clone, Copy, Debug)]
+pub struct Roi {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Roi {
+    /// Returns the (x, y) coordinates of the region's center
+    fn center(self) -> (f64, f64) {
+        (
+            self.x as f64 + self.width as f64 / 2.,
+            self.y as f64 + self.height as f64 / 2.,
+        )
     }
 }
 
-fn open_image(input_path: Option<String>) -> DynamicImage {
-    match input_path {
-        Some(in_path) => open_image_from_path(&in_path),
-        None => open_image_from_clipboard(),
+/// Parses a region of interest from `x,y,w,h`
+fn parse_roi(value: &str) -> Result<Roi, String> {
+    let numbers = value
+        .split(',')
+        .map(|number| number.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid region {value:?}: {e}"))?;
+    let [x, y, width, height] = numbers[..] else {
+        return Err(format!(
+            "invalid region {value:?}, expected x,y,w,h, e.g. 120,40,300,300"
+        ));
+    };
+    if width == 0 || height == 0 {
+        return Err(String::from("the region must not be empty"));
     }
+    Ok(Roi {
+        x,
+        y,
+        width,
+        height,
+    })
 }
 
-fn save_image_to_path(image: DynamicImage, output_path: &Path, temp_dir: PathBuf) {
-    if output_path.is_dir() || output_path.is_symlink() {
-        raise(&format!(
-            "{:?} is a directory or a symbolic link, cannot proceed",
-            output_path.display()
-        ))
+/// Parses a non-negative number of seconds for `--video-time`
+#[cfg(feature = "video")]
+fn parse_video_time(value: &str) -> Result<f64, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|e| format!("invalid number of seconds {value:?}: {e}"))?;
+    if !(seconds.is_finite() && seconds >= 0.) {
+        return Err(String::from(
+            "video time must be a non-negative number of seconds",
+        ));
     }
-    if output_path.is_file() {
-        match confirm(format!(
-            "{:?} is an existing file. replace? [y/n]: ",
-            output_path.display()
-        )) {
-            ConfirmResult::Continue => {
-                let backup_path =
-                    temp_dir.join(Path::new(&format!("BACKUP{}", get_timestamp_suffix())));
-                match fs::rename(&output_path, &backup_path) {
-                    Ok(_) => {
-                        println!(
-                            "Original file at {:?} backed up to: {:?}",
-                            output_path.display(),
-                            backup_path.display()
-                        )
-                    }
-                    Err(e) => raise(&format!(
-                        "Could not back up original file at {:?}: {e:?}",
-                        output_path.display()
-                    )),
-                }
-            }
-            ConfirmResult::Stop => {
-                println!("Please rerun with a different output path, or without an output path (to copy the result to the clipboard)");
-                process::exit(0)
-            }
-            ConfirmResult::IOError(e) => raise(&format!("Error while trying to read stdin: {e:?}")),
-        }
+    Ok(seconds)
+}
+
+/// Parses a WCAG contrast ratio for `--min-contrast`, from 1 to 21
+fn parse_contrast(value: &str) -> Result<f32, String> {
+    let ratio: f32 = value
+        .parse()
+        .map_err(|e| format!("invalid contrast ratio {value:?}: {e}"))?;
+    if !(1. ..=21.).contains(&ratio) {
+        return Err(String::from("contrast ratio must be from 1 to 21"));
     }
-    match image.save(output_path) {
-        Ok(_) => return println!("Saved image to {:?}!", output_path.display()),
-        Err(e) => raise(&format!(
-            "Could not save image to {:?}: {e:?}",
-            output_path.display()
-        )),
+    Ok(ratio)
+}
+
+/// Parses a finite fraction for `--offset-x` and `--offset-y`
+fn parse_offset(value: &str) -> Result<f32, String> {
+    let offset: f32 = value
+        .parse()
+        .map_err(|e| format!("invalid offset {value:?}: {e}"))?;
+    if !offset.is_finite() {
+        return Err(String::from("offset must be a finite number"));
     }
+    Ok(offset)
 }
 
-fn save_image_to_clipboard(image: DynamicImage) {
-    match confirm(String::from(
-        "Overwrite clipboard content with edited image? [y/n]: ",
-    )) {
-        ConfirmResult::Continue => {
-            let bytes = get_colors_alpha(&image).join(&[][..]);
-            let image_data = ImageData {
-                width: image.width() as usize,
-                height: image.height() as usize,
-                bytes: Cow::from(&bytes),
-            };
-            match Clipboard::new() {
-                Ok(mut clipboard) => {
-                    match clipboard.set_image(image_data) {
-                        Ok(_) => return println!("Edited image copied to clipboard!"),
-                        Err(e) => {
-                            raise(&format!("Could not copy edited image to clipboard: {e:?}"))
-                        }
-                    };
-                }
-                Err(e) => raise(&format!("Could not access clipboard: {e:?}")),
+/// Parses a blur radius, from 0 to `MAX_BLUR_RADIUS`
+fn parse_blur_radius(value: &str) -> Result<f32, String> {
+    let radius: f32 = value
+        .parse()
+        .map_err(|e| format!("invalid blur radius {value:?}: {e}"))?;
+    check_blur_radius(radius)
+}
+
+/// Returns `radius` if it is a usable blur radius, from 0 to `MAX_BLUR_RADIUS`
+pub(crate) fn check_blur_radius(radius: f32) -> Result<f32, String> {
+    if !(0. ..=MAX_BLUR_RADIUS).contains(&radius) {
+        return Err(format!(
+            "blur radius must be from 0 to {MAX_BLUR_RADIUS}, got {radius}"
+        ));
+    }
+    Ok(radius)
+}
+
+/// Parses a blur radius range from `<min>:<max>`
+fn parse_blur_range(value: &str) -> Result<(f32, f32), String> {
+    let (min_radius, max_radius) = value
+        .split_once(':')
+        .ok_or("expected <min>:<max>, e.g. 8:40")?;
+    let (min_radius, max_radius) = (
+        parse_blur_radius(min_radius)?,
+        parse_blur_radius(max_radius)?,
+    );
+    if min_radius > max_radius {
+        return Err(format!(
+            "the minimum radius {min_radius} is larger than the maximum {max_radius}"
+        ));
+    }
+    Ok((min_radius, max_radius))
+}
+
+/// Parses a glow from `<px>:#rrggbb`
+fn parse_glow(value: &str) -> Result<Glow, String> {
+    let (radius, color) = value
+        .split_once(':')
+        .ok_or("expected <px>:#rrggbb, e.g. 12:#ffffff")?;
+    let radius: f32 = radius
+        .parse()
+        .map_err(|e| format!("invalid glow radius {radius:?}: {e}"))?;
+    if !(radius > 0. && radius <= MAX_BLUR_RADIUS) {
+        return Err(format!(
+            "glow radius must be greater than 0 and at most {MAX_BLUR_RADIUS}"
+        ));
+    }
+    let color = parse_color(color).map_err(|e| format!("invalid glow color: {e}"))?;
+    Ok(Glow { radius, color })
+}
+
+/// Parses a color from `#rrggbb`
+fn parse_color(value: &str) -> Result<[u8; 3], String> {
+    let hex = value
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.is_ascii())
+        .ok_or(format!("{value:?} is not of the form #rrggbb"))?;
+    let mut rgb = [0; 3];
+    for (i, channel) in rgb.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|e| format!("{value:?} is not of the form #rrggbb: {e}"))?;
+    }
+    Ok(rgb)
+}
+
+/// An image (such as a logo) blended into a corner of the output
+#[derive(Clone, Debug)]
+pub struct Watermark {
+    pub image: DynamicImage,
+    pub position: Corner,
+    /// Opacity from 0 to 100, applied on top of the watermark's own alpha
+    pub opacity: u8,
+}
+
+/// How the output is placed on the clipboard
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ClipboardFormat {
+    /// Raw RGBA pixels
+    Bitmap,
+    /// PNG-encoded, as an HTML image with a data URI (for apps that paste PNG data better)
+    Png,
+    /// JPEG-encoded (see '--clipboard-quality'), as an HTML image with a data URI, a compact preview for
+    /// very large images
+    Jpeg,
+}
+
+/// Where a smaller image is placed within a larger one
+#[derive(Clone, Copy, Debug)]
+pub enum Align {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Returns the (x, y) offsets at which an image of size `fg` is placed within one of size `bg` (both as
+/// (width, height)), according to `align`; a dimension of `fg` larger than `bg` gets an offset of 0
+pub fn placement_offsets(bg: (u32, u32), fg: (u32, u32), align: Align) -> (u32, u32) {
+    let (x_slack, y_slack) = (bg.0.saturating_sub(fg.0), bg.1.saturating_sub(fg.1));
+    let x = match align {
+        Align::Left | Align::TopLeft | Align::BottomLeft => 0,
+        Align::Center | Align::Top | Align::Bottom => x_slack / 2,
+        Align::Right | Align::TopRight | Align::BottomRight => x_slack,
+    };
+    let y = match align {
+        Align::Top | Align::TopLeft | Align::TopRight => 0,
+        Align::Center | Align::Left | Align::Right => y_slack / 2,
+        Align::Bottom | Align::BottomLeft | Align::BottomRight => y_slack,
+    };
+    (x, y)
+}
+
+/// Returns the (x, y) offsets at which an image of size `fg` is placed within one of size `bg`, moved from
+/// the center by `bias` (as fractions of the space left over on each axis) and clamped so that it stays
+/// within `bg`
+pub fn biased_offsets(bg: (u32, u32), fg: (u32, u32), bias: (f32, f32)) -> (u32, u32) {
+    let (center_x, center_y) = placement_offsets(bg, fg, Align::Center);
+    let shift = |center: u32, slack: u32, bias: f32| {
+        let offset = center as f64 + (bias as f64 * slack as f64).round();
+        offset.clamp(0., slack as f64) as u32
+    };
+    (
+        shift(center_x, bg.0.saturating_sub(fg.0), bias.0),
+        shift(center_y, bg.1.saturating_sub(fg.1), bias.1),
+    )
+}
+
+/// What happens when the image does not fit into the background it is overlaid on
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Fit {
+    /// Fail with an error naming both sizes
+    Error,
+    /// Shrink the image, keeping its aspect ratio, until it fits, and center it
+    Contain,
+}
+
+/// Region of the upscaled image that the background's square crop keeps
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum CropGravity {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl CropGravity {
+    /// Returns the (x, y) offsets of a `side`×`side` crop of a `width`×`height` image
+    fn crop_offsets(self, width: u32, height: u32, side: u32) -> (u32, u32) {
+        let align = match self {
+            CropGravity::Center => Align::Center,
+            CropGravity::Top => Align::Top,
+            CropGravity::Bottom => Align::Bottom,
+            CropGravity::Left => Align::Left,
+            CropGravity::Right => Align::Right,
+        };
+        placement_offsets((width, height), (side, side), align)
+    }
+}
+
+/// Options controlling how an image is framed and encoded
+#[derive(Clone, Debug)]
+pub struct SqframeOptions {
+    /// What fills the square around the image
+    pub background: Background,
+    /// Number of dominant colors blended into a palette background, or clustered to pick the border color
+    pub palette_size: usize,
+    /// Blur radius of the background fill
+    pub blur: f32,
+    /// Number of Gaussian passes that `blur` is split over
+    pub blur_passes: u32,
+    /// Factor the background is shrunk by while it is built and blurred, 1 to build it at full size
+    pub bg_downscale: u32,
+    /// Whether to build the background at a small fixed size with a cheaper filter, for low-power devices
+    pub fast: bool,
+    /// Factor the image is enlarged by before it is framed, the result being shrunk back at the end to smooth
+    /// the edges of its effects, 1 to frame it at its own size
+    pub supersample: u32,
+    /// Whether to scale `blur` by the background's upscale factor
+    pub auto_blur: bool,
+    /// Range that the blur radius is picked from by the image's edge density, replacing `blur`, or `None` to
+    /// use `blur`
+    pub adaptive_blur: Option<(f32, f32)>,
+    /// Whether to keep the blurred background sharp at its center, blurring it progressively toward the edges
+    pub radial_focus: bool,
+    /// Whether to desaturate the blurred background
+    pub bg_grayscale: bool,
+    /// Whether to blur the background in linear light rather than on its sRGB values, which keeps bright
+    /// details from being dimmed by dark surroundings
+    pub linear_blur: bool,
+    /// Whether to blur the background with its edges mirrored outward, so that the pixels along each edge are not
+    /// weighted as if they extended past it
+    pub symmetric_blur: bool,
+    /// Glow drawn on the blurred background around the image, `None` for no glow
+    pub glow: Option<Glow>,
+    /// Width of a border drawn around the image in its dominant color, `None` for no border
+    pub border: Option<u32>,
+    /// Color that the image's transparent areas are flattened onto, `None` to keep them as they are
+    pub pad_color: Option<[u8; 3]>,
+    /// Radius of the framed square's rounded corners, which are made transparent outside, `None` for square
+    /// corners
+    pub outer_radius: Option<u32>,
+    /// Part of the image that the background is cropped from
+    pub crop_gravity: CropGravity,
+    /// How the side of the square is chosen
+    pub square_size: SquareSize,
+    /// What happens when the image does not fit into its background
+    pub fit: Fit,
+    /// Clockwise rotation of the input, applied before anything else
+    pub rotate: Rotation,
+    /// Mirroring of the input, applied after `rotate`, `None` to keep it as is
+    pub flip: Option<Flip>,
+    /// Whether to crop off letterbox or pillarbox bars (such as those of an earlier frame) before framing
+    pub detect_frame: bool,
+    /// Threshold for trimming near-uniform borders off the input, `None` to keep them
+    pub autocrop: Option<u8>,
+    /// Side of the output as a percentage of the input's larger dimension, `None` for 100
+    pub scale: Option<f32>,
+    /// Largest side the output square may have, the image being downscaled first to fit, `None` for no limit
+    pub max_dimension: Option<u32>,
+    /// Shift of the image from the center of the square, as fractions of the space left over on each axis
+    pub offset: (f32, f32),
+    /// Region of the input kept centered in the square (in place of `offset`) and uncropped where the square
+    /// allows, `None` to center the whole image
+    pub roi: Option<Roi>,
+    /// Format of the encoded output
+    pub format: ImageFormat,
+    /// Whether to dither paletted (GIF) output, and 16-bit images saved at 8 bits
+    pub dither: bool,
+    /// Bits per channel of the output (PNG only), `None` to keep the pipeline's depth
+    pub bit_depth: Option<BitDepth>,
+    /// Color channels of the output, `None` for RGBA with a transparent background and RGB otherwise
+    pub channels: Option<Channels>,
+    /// Physical resolution of the output in dots per inch (PNG and JPEG only), `None` to leave it unset
+    pub dpi: Option<u16>,
+    /// Quality (1-100) of a JPEG output, `None` for the encoder's default of 75
+    pub quality: Option<u8>,
+    /// Whether to run the whole pipeline even if the image is already square
+    pub always_frame: bool,
+    /// Whether to refuse any option that would crop or resample the image, so its pixels are kept as they are
+    pub only_pad: bool,
+    /// Image that the background is made from instead of the framed image, `None` to use the framed image
+    pub bg_source: Option<DynamicImage>,
+    /// Watermark to blend into the framed image
+    pub watermark: Option<Watermark>,
+    /// Text (such as the source filename) drawn in the bottom-left corner, truncated to fit
+    pub label: Option<String>,
+    /// Lowest WCAG contrast ratio between the image's edges and the background next to them before a
+    /// warning is printed, `None` to skip the check
+    pub min_contrast: Option<f32>,
+    /// Whether a contrast below `min_contrast` is an error rather than a warning
+    pub strict_contrast: bool,
+    /// Directory that each intermediate image of the pipeline is written to, `None` to write none
+    pub debug_dir: Option<PathBuf>,
+}
+
+impl Default for SqframeOptions {
+    fn default() -> Self {
+        SqframeOptions {
+            background: Background::Blur,
+            palette_size: 4,
+            blur: DEFAULT_BLUR,
+            blur_passes: 1,
+            bg_downscale: 1,
+            fast: false,
+            supersample: 1,
+            auto_blur: false,
+            adaptive_blur: None,
+            radial_focus: false,
+            bg_grayscale: false,
+            linear_blur: false,
+            symmetric_blur: false,
+            glow: None,
+            border: None,
+            pad_color: None,
+            outer_radius: None,
+            crop_gravity: CropGravity::Center,
+            square_size: SquareSize::Max,
+            fit: Fit::Error,
+            rotate: Rotation::None,
+            flip: None,
+            detect_frame: false,
+            autocrop: None,
+            scale: None,
+            max_dimension: None,
+            offset: (0., 0.),
+            roi: None,
+            format: ImageFormat::Png,
+            dither: false,
+            bit_depth: None,
+            channels: None,
+            dpi: None,
+            quality: None,
+            always_frame: false,
+            only_pad: false,
+            bg_source: None,
+            watermark: None,
+            label: None,
+            min_contrast: None,
+            strict_contrast: false,
+            debug_dir: None,
+        }
+    }
+}
+
+impl SqframeOptions {
+    /// Checks that the output options can be applied to the output format
+    pub fn validate(&self) -> Result<(), SqframeError> {
+        if self.bit_depth.is_some() && self.format != ImageFormat::Png {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "a bit depth can only be set for PNG output",
+            )));
+        }
+        if self.dpi.is_some() && !matches!(self.format, ImageFormat::Png | ImageFormat::Jpeg) {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "a DPI can only be set for PNG and JPEG output",
+            )));
+        }
+        if matches!(self.background, Background::None)
+            && !matches!(self.format, ImageFormat::Png | ImageFormat::Tiff)
+        {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "a transparent background needs PNG or TIFF output",
+            )));
+        }
+        match self.channels {
+            Some(Channels::Rgba)
+                if !matches!(self.format, ImageFormat::Png | ImageFormat::Tiff) =>
+            {
+                return Err(SqframeError::InvalidArgs(String::from(
+                    "RGBA output needs PNG or TIFF output",
+                )));
             }
+            Some(Channels::Rgb) if matches!(self.background, Background::None) => {
+                return Err(SqframeError::InvalidArgs(String::from(
+                    "a transparent background needs RGBA output",
+                )));
+            }
+            _ => {}
         }
-        ConfirmResult::Stop => {
-            println!("Please rerun with the clipboard content backed up, or with an output path specified (see '--help')");
-            process::exit(0)
+        if self.outer_radius.is_some() {
+            if !matches!(self.format, ImageFormat::Png | ImageFormat::Tiff) {
+                return Err(SqframeError::InvalidArgs(String::from(
+                    "rounded outer corners need PNG or TIFF output",
+                )));
+            }
+            if self.channels == Some(Channels::Rgb) {
+                return Err(SqframeError::InvalidArgs(String::from(
+                    "rounded outer corners need RGBA output",
+                )));
+            }
         }
-        ConfirmResult::IOError(e) => raise(&format!("Could not read stdin: {e:?}")),
+        Ok(())
     }
 }
 
-fn save_image(image: DynamicImage, output_path: Option<String>) {
-    let temp_dir = env::temp_dir();
-    match output_path {
-        Some(out_path) => save_image_to_path(image, Path::new(&out_path), temp_dir),
-        None => save_image_to_clipboard(image),
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the dimensions, format, color type, EXIF orientation and resolution of an image without framing it
+    Info {
+        /// Image file to inspect
+        path: String,
+    },
+    /// Move the most recent backup of a file replaced by an earlier run back to its place
+    Restore {
+        /// File to restore, as given as the output path of that run
+        path: String,
+    },
 }
 
-pub fn run(args: Args) {
-    let image = open_image(args.input_path);
-    println!("Creating blurred background...");
-    let (width, height) = (image.width(), image.height());
-    let sqside = max(width, height);
-    let factor = min(width, height);
-    let resized_width = width * sqside / factor;
-    let resized_height = height * sqside / factor;
-    let mut bg = image.resize(resized_width, resized_height, FilterType::Triangle);
-    println!("Upscale: done");
-    bg = bg.crop(
-        (resized_width - sqside) / 2,
-        (resized_height - sqside) / 2,
-        sqside,
-        sqside,
+/// Environment variable naming the directory that replaced outputs are backed up to
+const BACKUP_DIR_VAR: &str = "SQFRAME_TMPDIR";
+
+/// Returns the directory that replaced outputs are backed up to, `$SQFRAME_TMPDIR` if it is set and the
+/// system's temporary directory otherwise
+fn backup_dir() -> PathBuf {
+    env::var_os(BACKUP_DIR_VAR)
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(env::temp_dir, PathBuf::from)
+}
+
+/// Returns the start of the names of the backups of the file at `path`, identifying it by a hash of its absolute
+/// path so that `restore` can find them
+fn backup_prefix(path: &Path) -> String {
+    let absolute = path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    format!(
+        "BACKUP-{:08x}",
+        crc32fast::hash(absolute.as_os_str().as_encoded_bytes())
+    )
+}
+
+/// Moves the most recent backup of the file at `path` in the backup directory back to `path`, replacing the file
+/// there
+fn restore(path: &Path) -> Result<(), SqframeError> {
+    let dir = backup_dir();
+    let prefix = backup_prefix(path);
+    let entries = fs::read_dir(&dir).map_err(SqframeError::io(format!(
+        "could not read backup directory {:?}",
+        dir.display()
+    )))?;
+    let latest = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let timestamp = match name.to_str()?.strip_prefix(&prefix)? {
+                "" => 0,
+                suffix => suffix.strip_prefix('-')?.parse::<u128>().ok()?,
+            };
+            Some((timestamp, entry.path()))
+        })
+        .max();
+    let Some((_, backup_path)) = latest else {
+        return Err(SqframeError::Io {
+            context: format!("no backup of {:?} in {:?}", path.display(), dir.display()),
+            source: io::ErrorKind::NotFound.into(),
+        });
+    };
+    move_file(&backup_path, path).map_err(SqframeError::io(format!(
+        "could not move backup {:?} to {:?}",
+        backup_path.display(),
+        path.display()
+    )))?;
+    progress!(
+        "Restored {:?} from {:?}",
+        path.display(),
+        backup_path.display()
     );
-    println!("Square crop: done");
-    bg = blur(&bg, 16.);
-    println!("Gaussian blur: done");
-    println!("Background created");
-    println!("Constructing final image...");
-    let final_image = overlay(&bg, &image);
-    println!("Done!");
-    save_image(final_image, args.output_path);
+    Ok(())
+}
+
+/// Moves the file at `from` to `to`, copying it when they are on different filesystems (such as a backup
+/// directory on another disk)
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// Exit statuses, listed at the end of `--help`
+const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0  Success, or the user chose not to overwrite the output
+  2  Invalid arguments
+  3  The input could not be decoded
+  4  A file could not be read or written
+  5  The clipboard could not be read or written
+  6  The output could not be encoded
+  7  The image's dimensions cannot be framed
+  8  The image contrasts too little with its background (with --strict)";
+
+/// A tool to create a square frame with a blurred background for any image, to match the aspect ratio 1:1
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    after_help = EXIT_STATUS_HELP
+)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Input file path, defaults to clipboard
+    #[arg(short, long)]
+    input_path: Option<String>,
+
+    /// Capture the primary screen instead of reading a file or the clipboard (on Wayland, only where the
+    /// compositor allows screen capture)
+    #[cfg(feature = "capture")]
+    #[arg(long, conflicts_with = "input_path")]
+    screenshot: bool,
+
+    /// Treat the input as a video and frame the frame shown SECONDS into it, extracted with ffmpeg (which
+    /// must be on the PATH)
+    #[cfg(feature = "video")]
+    #[arg(long, value_name = "SECONDS", requires = "input_path", conflicts_with_all = ["keep_metadata", "convert_srgb"], value_parser = parse_video_time)]
+    video_time: Option<f64>,
+
+    /// Read the input from DATA, a base64-encoded image or data URI, or from stdin if DATA is "-" (for inputs
+    /// larger than a command line argument may be)
+    #[arg(long, value_name = "DATA", conflicts_with_all = ["input_path", "watch"])]
+    input_base64: Option<String>,
+
+    /// Watch DIR and frame every image added to it into the output path (a directory), each keeping its
+    /// own format, until interrupted
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "output_path",
+        conflicts_with = "input_path"
+    )]
+    watch: Option<PathBuf>,
+
+    /// Write a manifest of a zip input's images to PATH, listing each one's output path, dimensions and
+    /// whether it was framed, as JSON if PATH ends in .json and as CSV otherwise
+    #[arg(long, value_name = "PATH", requires = "input_path")]
+    manifest: Option<PathBuf>,
+
+    /// (Experimental) Serve an HTTP endpoint on PORT of localhost that frames the image POSTed to /frame and
+    /// responds with it in the same format, until interrupted
+    #[cfg(feature = "server")]
+    #[arg(long, value_name = "PORT", conflicts_with_all = ["input_path", "output_path", "watch"])]
+    serve: Option<u16>,
+
+    /// Decode the input as this format (e.g. png, jpg) instead of detecting it from the file's contents
+    #[arg(long, value_name = "FORMAT", requires = "input_path", value_parser = parse_input_format)]
+    input_format: Option<ImageFormat>,
+
+    /// Frame of an animated GIF input to frame, counting from 0 [default: 0]
+    #[arg(long, value_name = "N", requires = "input_path")]
+    frame: Option<usize>,
+
+    /// Output file path, defaults to clipboard
+    #[arg(short, long)]
+    output_path: Option<String>,
+
+    /// Also copy the output to the clipboard after saving it to the output path
+    #[arg(long, requires = "output_path", conflicts_with = "sizes")]
+    also_clipboard: bool,
+
+    /// Open the output in the default image viewer once it is saved, or the directory holding the outputs
+    /// when there are several (a zip input or '--sizes')
+    #[arg(long, requires = "output_path", conflicts_with_all = ["watch", "base64"])]
+    open: bool,
+
+    /// How the output is placed on the clipboard
+    #[arg(long, value_enum, default_value_t = ClipboardFormat::Bitmap)]
+    clipboard_format: ClipboardFormat,
+
+    /// JPEG quality (1-100) of '--clipboard-format jpeg' and of compressed previews of oversized bitmaps
+    #[arg(long, value_name = "QUALITY", default_value_t = 85, value_parser = clap::value_parser!(u8).range(1..=100))]
+    clipboard_quality: u8,
+
+    /// Copy bitmaps to the clipboard with their red and blue channels swapped (BGRA), for apps that paste them
+    /// with red and blue swapped
+    #[arg(long)]
+    compat_clipboard: bool,
+
+    /// Seconds to wait for the clipboard before giving up
+    #[arg(long, value_name = "SECONDS", default_value = "10", value_parser = parse_timeout)]
+    timeout: Duration,
+
+    /// Number of times to try accessing the clipboard before giving up
+    #[arg(long, hide = true, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    clipboard_attempts: u32,
+
+    /// Milliseconds to wait before retrying the clipboard, doubling after each failed attempt
+    #[arg(long, hide = true, default_value_t = 100)]
+    clipboard_retry_delay: u64,
+
+    /// Print the output as a base64 PNG data URI, alone on stdout (progress messages go to stderr), instead of
+    /// saving it
+    #[arg(long, conflicts_with = "output_path")]
+    base64: bool,
+
+    /// Strip EXIF and ICC metadata from the saved output (default)
+    #[arg(long, overrides_with = "keep_metadata")]
+    strip_metadata: bool,
+
+    /// Retain the input's EXIF and ICC metadata in the saved output (PNG and JPEG only)
+    #[arg(long, overrides_with = "strip_metadata")]
+    keep_metadata: bool,
+
+    /// Write this EXIF orientation (1-8) to the output (PNG and JPEG only), leaving its pixels as they are, for
+    /// apps that rotate images by their orientation tag
+    #[arg(long, value_name = "ORIENTATION", value_parser = clap::value_parser!(u16).range(1..=8))]
+    set_orientation: Option<u16>,
+
+    /// Treat the input's pixels as sRGB, ignoring any embedded color profile (default)
+    #[arg(long, overrides_with = "convert_srgb")]
+    assume_srgb: bool,
+
+    /// Convert the input's pixels from its embedded (matrix/TRC) ICC color profile to sRGB before processing
+    #[arg(long, overrides_with = "assume_srgb", requires = "input_path")]
+    convert_srgb: bool,
+
+    /// What fills the square around the image
+    #[arg(long, value_enum, default_value_t = Background::Blur)]
+    background: Background,
+
+    /// Number of dominant colors blended into '--background palette', or clustered to pick '--border-auto''s color
+    #[arg(long, value_name = "K", default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..=16))]
+    palette_size: u8,
+
+    /// Blur radius for the fill bars around the image [default: 16]
+    #[arg(long, value_name = "RADIUS", value_parser = parse_blur_radius)]
+    bar_blur: Option<f32>,
+
+    /// Split the blur into N passes of radius / √N each, which add up to the same overall softness as a
+    /// single pass of the full radius
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    blur_passes: u32,
+
+    /// Run the blur (and parallel decoding) on at most N threads instead of one per core; '--threads 1'
+    /// processes everything serially, which makes timings easier to compare
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    threads: Option<u32>,
+
+    /// Build and blur the background at 1/FACTOR of its size and enlarge it afterwards, which cuts the work on
+    /// large images roughly FACTOR² times; the lost detail is hidden by large blur radii, but small radii or
+    /// large factors can leave the background visibly softer or banded
+    #[arg(long, value_name = "FACTOR", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    bg_downscale: u32,
+
+    /// Build and blur the background at most 256 pixels wide, shrinking the image for it with a cheaper filter,
+    /// and enlarge it afterwards; much faster on large images or low-power devices, but the background comes
+    /// out softer and can show banding or blockiness, especially with small blur radii
+    #[arg(long)]
+    fast: bool,
+
+    /// Frame the image at FACTOR times its size and shrink the result back, which smooths the edges of
+    /// '--outer-radius', '--border-auto' and '--glow' at the cost of FACTOR² times the work and a slightly
+    /// softer image
+    #[arg(long, value_name = "FACTOR", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=4))]
+    supersample: u32,
+
+    /// Part of the image that the blurred background is cropped from
+    #[arg(long, value_enum, default_value_t = CropGravity::Center)]
+    crop_gravity: CropGravity,
+
+    /// Frame the image on a background, or just crop it to a square (same as '--square-size min')
+    #[arg(long, value_enum, default_value_t = Mode::Frame)]
+    mode: Mode,
+
+    /// Side of the square: the larger dimension (upscaling the background), the smaller one or their
+    /// average (cropping the image's longer side to fit)
+    #[arg(long, value_enum, default_value_t = SquareSize::Max)]
+    square_size: SquareSize,
+
+    /// What to do if the image does not fit into the background it is placed on: fail, or shrink it to fit.
+    /// The square is sized to hold the image, so this only guards against an image that would be cut off
+    #[arg(long, value_enum, default_value_t = Fit::Error)]
+    fit: Fit,
+
+    /// Scale the blur radius by the background's upscale factor, i.e. radius × max(w, h) / min(w, h),
+    /// so the background looks equally soft regardless of the input's aspect ratio
+    #[arg(long)]
+    auto_blur: bool,
+
+    /// Pick the blur radius between MIN and MAX by how busy the image is (its average edge strength), blurring
+    /// detailed images more and smooth ones less, instead of using a fixed radius
+    #[arg(long, value_name = "MIN:MAX", conflicts_with = "bar_blur", value_parser = parse_blur_range)]
+    adaptive_blur: Option<(f32, f32)>,
+
+    /// Keep the blurred background sharp at its center and blur it progressively toward the edges, for a
+    /// tilt-shift look, instead of blurring it evenly
+    #[arg(long)]
+    radial_focus: bool,
+
+    /// Desaturate the blurred background, leaving the image itself in color
+    #[arg(long)]
+    bg_grayscale: bool,
+
+    /// Blur the background in linear light instead of on its sRGB values, so that bright details spread into
+    /// dark surroundings instead of being dimmed by them (slower)
+    #[arg(long)]
+    linear_blur: bool,
+
+    /// Mirror the background's edges outward before blurring it and crop them off afterwards, so that details
+    /// along an edge are not smeared into a band as if they extended past it
+    #[arg(long)]
+    symmetric_blur: bool,
+
+    /// Draw a soft glow of radius PX and color #RRGGBB on the background around the image's edges
+    #[arg(long, value_name = "PX:#RRGGBB", value_parser = parse_glow)]
+    glow: Option<Glow>,
+
+    /// Draw a border PX pixels wide around the image, in the most common of its dominant colors (see
+    /// '--palette-size')
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(1..))]
+    border_auto: Option<u32>,
+
+    /// Show this color through the image's transparent areas, e.g. a matte behind a logo, instead of
+    /// whatever color they hold
+    #[arg(long, value_name = "#RRGGBB", value_parser = parse_color)]
+    pad_color: Option<[u8; 3]>,
+
+    /// Round the corners of the framed square with this radius, transparent outside them (e.g. for app
+    /// icons); needs PNG or TIFF output
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(1..))]
+    outer_radius: Option<u32>,
+
+    /// Rotate the input clockwise by this many degrees before framing
+    #[arg(long, value_enum, value_name = "DEGREES", default_value_t = Rotation::None)]
+    rotate: Rotation,
+
+    /// Mirror the input before framing
+    #[arg(long, value_enum)]
+    flip: Option<Flip>,
+
+    /// Crop off letterbox or pillarbox bars (uniform or blurred, e.g. from an earlier sqframe run) before
+    /// framing, instead of framing them again
+    #[arg(long)]
+    detect_existing_frame: bool,
+
+    /// Trim near-uniform borders (e.g. from scans) off the input before framing
+    #[arg(long)]
+    autocrop: bool,
+
+    /// Maximum per-channel difference from the corner color for a border pixel to be trimmed
+    #[arg(
+        long,
+        default_value_t = 16,
+        value_name = "THRESHOLD",
+        requires = "autocrop"
+    )]
+    autocrop_threshold: u8,
+
+    /// Size the output square as a percentage of the input's larger dimension
+    #[arg(long, value_name = "PERCENT", value_parser = parse_scale)]
+    scale: Option<f32>,
+
+    /// Downscale the image first if needed so that the output square's side is at most PX, e.g. for very wide
+    /// or tall panoramas whose square would be too large to build
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(1..))]
+    max_dimension: Option<u32>,
+
+    /// Move the image right (or left, if negative) of center by this fraction of the space left over
+    /// beside it, clamped to keep it within the square
+    #[arg(long, value_name = "FRACTION", default_value_t = 0., allow_negative_numbers = true, value_parser = parse_offset)]
+    offset_x: f32,
+
+    /// Move the image down (or up, if negative) from center by this fraction of the space left over
+    /// above and below it, clamped to keep it within the square
+    #[arg(long, value_name = "FRACTION", default_value_t = 0., allow_negative_numbers = true, value_parser = parse_offset)]
+    offset_y: f32,
+
+    /// Keep this region of the input (x,y,w,h in pixels) centered in the square, and uncropped by
+    /// '--square-size min/avg' where it fits, instead of centering the whole image
+    #[arg(long, value_name = "X,Y,W,H", conflicts_with_all = ["offset_x", "offset_y", "rotate", "flip", "autocrop", "detect_existing_frame"], value_parser = parse_roi)]
+    roi: Option<Roi>,
+
+    /// Run the full framing pipeline even if the image is already square
+    #[arg(long)]
+    always_frame: bool,
+
+    /// Only pad the image to a square at its native size, never cropping or resampling it (for pixel-perfect
+    /// screenshots)
+    #[arg(long, conflicts_with_all = ["square_size", "scale", "autocrop", "detect_existing_frame", "sizes"])]
+    only_pad: bool,
+
+    /// Make the background (blurred, palette or average) from the image at PATH instead of the input, to give
+    /// a series of images a common backdrop
+    #[arg(long, value_name = "PATH")]
+    bg_from: Option<String>,
+
+    /// Image (such as a logo) to blend into a corner of the output
+    #[arg(long, value_name = "PATH")]
+    watermark: Option<String>,
+
+    /// Corner of the output that the watermark is placed in
+    #[arg(long, value_enum, default_value_t = Corner::BottomRight, requires = "watermark")]
+    watermark_position: Corner,
+
+    /// Opacity of the watermark, from 0 to 100
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100), requires = "watermark")]
+    watermark_opacity: u8,
+
+    /// Draw the input's file name in the bottom-left corner of the output, for proofing
+    #[arg(long, requires = "input_path")]
+    label_filename: bool,
+
+    /// Also save a thumbnail of the output, downscaled to at most SIZE pixels per side, to PATH
+    #[arg(long, value_name = "SIZE:PATH", value_parser = parse_thumbnail)]
+    thumbnail: Option<Thumbnail>,
+
+    /// Save the output at each of these sizes (in pixels per side) instead of its own, to the output path with
+    /// "{size}" replaced by the size (or "-<size>" appended to the file name if there is no "{size}")
+    #[arg(long, value_name = "SIZES", value_delimiter = ',', requires = "output_path", value_parser = clap::value_parser!(u32).range(1..))]
+    sizes: Vec<u32>,
+
+    /// Print the input's mean color, luminance range and luminance histogram to stderr
+    #[arg(long)]
+    stats: bool,
+
+    /// Write each intermediate image (foreground, upscaled, cropped, blurred, background, overlaid) to DIR as
+    /// numbered PNG files, to see where an unexpected result comes from
+    #[arg(long, value_name = "DIR")]
+    debug_dir: Option<PathBuf>,
+
+    /// Warn if the WCAG contrast ratio (1 to 21) between the image's edges and the background next to them is
+    /// below RATIO, e.g. 1.5
+    #[arg(long, value_name = "RATIO", value_parser = parse_contrast)]
+    min_contrast: Option<f32>,
+
+    /// Fail instead of warning when the contrast is below --min-contrast
+    #[arg(long, requires = "min_contrast")]
+    strict: bool,
+
+    /// Leave output files alone if their contents already match what would be written
+    #[arg(long)]
+    skip_unchanged: bool,
+
+    /// Never overwrite existing files: fail if the output exists (or, for a zip input, skip the image)
+    #[arg(long)]
+    no_clobber: bool,
+
+    /// Language of the prompts asking before a file or the clipboard is replaced [default: from LC_ALL,
+    /// LC_MESSAGES or LANG, or English]
+    #[arg(long, value_enum)]
+    lang: Option<Lang>,
+
+    /// Print errors without colors, as is also done when NO_COLOR is set or stderr is not a terminal
+    #[arg(long)]
+    no_color: bool,
+
+    /// Read each output file back after saving it, failing if it does not hold what was written or does not
+    /// decode (catches silent disk or encoder corruption, at the cost of extra I/O)
+    #[arg(long)]
+    verify: bool,
+
+    /// Replace an existing output file (after backing it up) or the clipboard's content without asking,
+    /// for use in scripts
+    #[arg(long, conflicts_with = "no_clobber")]
+    force: bool,
+
+    /// When an existing output file is backed up, print just the backup's path on its own line instead of a
+    /// message, for scripts that may restore it; progress messages and prompts go to stderr, leaving stdout to
+    /// the backup paths
+    #[arg(long)]
+    print_backup_path: bool,
+
+    /// Also save the original image and the output side by side, scaled to equal heights, to PATH
+    #[arg(long, value_name = "PATH", value_parser = parse_compare)]
+    compare: Option<(PathBuf, ImageFormat)>,
+
+    /// Bits per channel of the output, PNG only
+    #[arg(long)]
+    bit_depth: Option<BitDepth>,
+
+    /// Color channels of the output [default: rgba with '--background none', rgb otherwise]
+    #[arg(long, value_enum)]
+    output_channels: Option<Channels>,
+
+    /// Physical resolution to record in the output (PNG and JPEG only), in dots per inch, for printing
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    dpi: Option<u16>,
+
+    /// JPEG quality (1-100) of the output [default: 75]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+
+    /// Output settings for a purpose, overridden by explicitly set '--quality', '--dpi', '--bit-depth' and
+    /// '--strip-metadata'; the format still follows the output path's extension
+    #[arg(long, value_enum)]
+    preset: Option<Preset>,
+
+    /// Apply Floyd–Steinberg dithering when saving to a paletted format (GIF), and an ordered dither when a
+    /// 16-bit image is saved at 8 bits ('--bit-depth 8')
+    #[arg(long)]
+    dither: bool,
+}
+
+impl Args {
+    /// Returns the framing options requested on the command line, with the output format taken from
+    /// the output path's extension (PNG for the clipboard, and as a placeholder for zip input and '--watch',
+    /// whose images keep their own formats)
+    fn options(&self) -> Result<SqframeOptions, SqframeError> {
+        let batch = self.input_path.as_deref().is_some_and(is_zip) || self.watch.is_some();
+        let format = match &self.output_path {
+            Some(_) if batch => ImageFormat::Png,
+            Some(out_path) => ImageFormat::from_path(out_path).map_err(|e| {
+                SqframeError::InvalidArgs(format!(
+                    "could not determine the output format of {out_path:?}: {e}"
+                ))
+            })?,
+            None => ImageFormat::Png,
+        };
+        if self.mode == Mode::Crop && !matches!(self.square_size, SquareSize::Max) {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "'--mode crop' always crops to the smaller dimension, so '--square-size' cannot be set",
+            )));
+        }
+        if self.bg_from.is_some() && matches!(self.background, Background::None) {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "'--bg-from' needs a background other than '--background none'",
+            )));
+        }
+        let bg_source = match &self.bg_from {
+            Some(path) => Some(open_image_from_path(path, None, None)?),
+            None => None,
+        };
+        let watermark = match &self.watermark {
+            Some(path) => Some(Watermark {
+                image: open_image_from_path(path, None, None)?,
+                position: self.watermark_position,
+                opacity: self.watermark_opacity,
+            }),
+            None => None,
+        };
+        Ok(SqframeOptions {
+            background: self.background,
+            palette_size: self.palette_size as usize,
+            blur: self.bar_blur.unwrap_or(DEFAULT_BLUR),
+            blur_passes: self.blur_passes,
+            bg_downscale: self.bg_downscale,
+            fast: self.fast,
+            supersample: self.supersample,
+            auto_blur: self.auto_blur,
+            adaptive_blur: self.adaptive_blur,
+            radial_focus: self.radial_focus,
+            bg_grayscale: self.bg_grayscale,
+            linear_blur: self.linear_blur,
+            symmetric_blur: self.symmetric_blur,
+            glow: self.glow,
+            border: self.border_auto,
+            pad_color: self.pad_color,
+            outer_radius: self.outer_radius,
+            crop_gravity: self.crop_gravity,
+            square_size: match self.mode {
+                Mode::Frame => self.square_size,
+                Mode::Crop => SquareSize::Min,
+            },
+            fit: self.fit,
+            rotate: self.rotate,
+            flip: self.flip,
+            detect_frame: self.detect_existing_frame,
+            autocrop: self.autocrop.then_some(self.autocrop_threshold),
+            scale: self.scale,
+            max_dimension: self.max_dimension,
+            offset: (self.offset_x, self.offset_y),
+            roi: self.roi,
+            format,
+            dither: self.dither,
+            // the preset's settings fill in what isn't set explicitly, where the format supports them
+            bit_depth: self.bit_depth.or(self
+                .preset
+                .and_then(Preset::bit_depth)
+                .filter(|_| format == ImageFormat::Png)),
+            channels: self.output_channels,
+            dpi: self.dpi.or(self
+                .preset
+                .and_then(Preset::dpi)
+                .filter(|_| matches!(format, ImageFormat::Png | ImageFormat::Jpeg))),
+            quality: self.quality.or(self.preset.map(Preset::quality)),
+            always_frame: self.always_frame && self.mode == Mode::Frame,
+            only_pad: self.only_pad,
+            bg_source,
+            watermark,
+            label: self
+                .label_filename
+                .then(|| self.input_path.as_deref().map(Path::new))
+                .flatten()
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned()),
+            min_contrast: self.min_contrast,
+            strict_contrast: self.strict,
+            debug_dir: self.debug_dir.clone(),
+        })
+    }
+}
+
+/// Returns a vector containing arrays of length 3 (R, G, B) corresponding to the pixels in the image,
+/// allocated once at its final size and copied straight from the image's buffer if it is already RGB8
+fn get_colors(image: &DynamicImage) -> Vec<[u8; 3]> {
+    let converted;
+    let rgb = match image.as_rgb8() {
+        Some(rgb) => rgb,
+        None => {
+            converted = image.to_rgb8();
+            &converted
+        }
+    };
+    rgb.pixels().map(|pixel| pixel.0).collect()
+}
+
+/// Prints the mean color, luminance range and a coarse luminance histogram of the image to stderr
+fn print_stats(image: &DynamicImage) {
+    const BUCKETS: usize = 8;
+    const BAR_WIDTH: usize = 40;
+    let colors = get_colors(image);
+    if colors.is_empty() {
+        return;
+    }
+    let mut sums = [0u64; 3];
+    let (mut min_luma, mut max_luma) = (u8::MAX, u8::MIN);
+    let mut histogram = [0usize; BUCKETS];
+    for color in &colors {
+        for channel in 0..3 {
+            sums[channel] += color[channel] as u64;
+        }
+        let luma = (0.2126 * color[0] as f32 + 0.7152 * color[1] as f32 + 0.0722 * color[2] as f32)
+            .round() as u8;
+        min_luma = min(min_luma, luma);
+        max_luma = max(max_luma, luma);
+        histogram[luma as usize * BUCKETS / 256] += 1;
+    }
+    let mean = sums.map(|sum| sum as f64 / colors.len() as f64);
+    eprintln!("Mean RGB:  {:.1}, {:.1}, {:.1}", mean[0], mean[1], mean[2]);
+    eprintln!("Luminance: {min_luma} to {max_luma}");
+    let largest = histogram.iter().copied().max().unwrap_or(1);
+    for (bucket, count) in histogram.iter().enumerate() {
+        let (low, high) = (bucket * 256 / BUCKETS, (bucket + 1) * 256 / BUCKETS - 1);
+        eprintln!(
+            "{low:>3}-{high:<3} {:<BAR_WIDTH$} {:>5.1}%",
+            "#".repeat(count * BAR_WIDTH / largest),
+            *count as f64 * 100. / colors.len() as f64
+        );
+    }
+}
+
+/// Returns a blurred (Gaussian blur) copy of the image, with `intensity` being the blur radius, applied in
+/// `passes` passes of radius `intensity / √passes` (the variances of successive Gaussian blurs add up). Besides
+/// the image, it holds two RGB8 copies of it (3 bytes per pixel each) at a time: the pixels being blurred, which
+/// then become the output's buffer, and fastblur's scratch buffer
+fn blur(image: &DynamicImage, intensity: f32, passes: u32) -> Result<DynamicImage, SqframeError> {
+    let (width, height) = (image.width(), image.height());
+    let mut colors = get_colors(image);
+    blur_colors(&mut colors, (width, height), intensity, passes)?;
+    // the blurred pixels become the output's buffer as they are, rather than being copied into a new one
+    let blurred_image_buffer = RgbImage::from_raw(width, height, colors.into_flattened())
+        .ok_or_else(|| {
+            SqframeError::Dimension(format!(
+                "blurred buffer does not hold {width}x{height} pixels"
+            ))
+        })?;
+    Ok(DynamicImage::ImageRgb8(blurred_image_buffer))
+}
+
+/// Blurs `colors`, the pixels of a `width`×`height` image, in place as `blur` describes
+fn blur_colors(
+    colors: &mut Vec<[u8; 3]>,
+    (width, height): (u32, u32),
+    intensity: f32,
+    passes: u32,
+) -> Result<(), SqframeError> {
+    // `gaussian_blur` indexes the buffer as `width * height` pixels without checking its length
+    if colors.len() as u64 != width as u64 * height as u64 {
+        return Err(SqframeError::Dimension(format!(
+            "blur buffer holds {} pixels, expected {width}x{height}",
+            colors.len()
+        )));
+    }
+    // `gaussian_blur` also underflows on an image without pixels, which there is nothing to blur in anyway
+    if colors.is_empty() {
+        return Ok(());
+    }
+    let pass_intensity = intensity / (passes as f32).sqrt();
+    for _ in 0..passes {
+        gaussian_blur(colors, width as usize, height as usize, pass_intensity);
+    }
+    Ok(())
+}
+
+/// Like `blur`, but averages the image's colors in linear light, with the same approximation of a Gaussian by
+/// three box blurs that `gaussian_blur` uses
+fn blur_linear(
+    image: &DynamicImage,
+    intensity: f32,
+    passes: u32,
+) -> Result<DynamicImage, SqframeError> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut pixels = color::srgb_to_linear(&image.to_rgb8());
+    if pixels.is_empty() {
+        return Ok(image.clone());
+    }
+    let pass_intensity = intensity / (passes as f32).sqrt();
+    // columns are blurred as the rows of a transposed copy, so that every line is a separate chunk that rayon
+    // can hand to its own thread
+    let mut transposed = vec![[0.; 3]; pixels.len()];
+    for _ in 0..passes {
+        for size in box_sizes(pass_intensity) {
+            let radius = (size - 1) / 2;
+            blur_rows(&mut pixels, width, radius);
+            transpose(&pixels, &mut transposed, width);
+            blur_rows(&mut transposed, height, radius);
+            transpose(&transposed, &mut pixels, height);
+        }
+    }
+    Ok(DynamicImage::ImageRgb8(color::linear_to_srgb(
+        &pixels,
+        width as u32,
+    )))
+}
+
+/// Box-blurs every `width`-pixel row of `pixels` with `radius`, in parallel
+fn blur_rows(pixels: &mut [[f32; 3]], width: usize, radius: usize) {
+    pixels
+        .par_chunks_mut(width)
+        .for_each_init(Vec::new, |line, row| {
+            box_blur_line(row, 0, 1, width, radius, line)
+        });
+}
+
+/// Writes the transpose of `pixels`, made of `width`-pixel rows, into `transposed`
+fn transpose(pixels: &[[f32; 3]], transposed: &mut [[f32; 3]], width: usize) {
+    let height = pixels.len() / width;
+    transposed
+        .par_chunks_mut(height)
+        .enumerate()
+        .for_each(|(x, column)| {
+            for (y, pixel) in column.iter_mut().enumerate() {
+                *pixel = pixels[y * width + x];
+            }
+        });
+}
+
+/// Returns the widths of the three box blurs that approximate a Gaussian blur of `sigma`, as `gaussian_blur`
+/// computes them
+fn box_sizes(sigma: f32) -> [usize; 3] {
+    if sigma <= 0. {
+        return [1; 3];
+    }
+    let ideal_width = 2. * sigma + 1.;
+    let mut lower = ideal_width.floor() as usize;
+    if lower.is_multiple_of(2) {
+        lower -= 1;
+    }
+    let lower_f = lower as f32;
+    let lower_count = ((12. * sigma * sigma - 3. * lower_f * lower_f - 12. * lower_f - 9.)
+        / (-4. * lower_f - 4.))
+        .round()
+        .max(0.) as usize;
+    [0, 1, 2].map(|i| if i < lower_count { lower } else { lower + 2 })
+}
+
+/// Replaces each of the `len` pixels at `start`, `start + stride`, ... with the mean of the pixels within
+/// `radius` of it, extending the line's end pixels beyond its ends; `line` is scratch space
+fn box_blur_line(
+    pixels: &mut [[f32; 3]],
+    start: usize,
+    stride: usize,
+    len: usize,
+    radius: usize,
+    line: &mut Vec<[f32; 3]>,
+) {
+    if radius == 0 || len == 0 {
+        return;
+    }
+    line.clear();
+    line.extend((0..len).map(|i| pixels[start + i * stride]));
+    let at = |i: isize| line[i.clamp(0, len as isize - 1) as usize];
+    // the first window holds `radius` copies of the first pixel, the pixels up to `radius` and, if the line is
+    // shorter than that, copies of the last pixel, counted without walking a radius that may dwarf the line
+    let (first, last) = (line[0], line[len - 1]);
+    let beyond_end = radius.saturating_sub(len - 1) as f32;
+    let mut sum: [f32; 3] = array::from_fn(|c| first[c] * radius as f32 + last[c] * beyond_end);
+    for value in &line[..min(radius + 1, len)] {
+        (0..3).for_each(|c| sum[c] += value[c]);
+    }
+    let radius = radius as isize;
+    let scale = 1. / (2 * radius + 1) as f32;
+    for i in 0..len as isize {
+        pixels[start + i as usize * stride] = sum.map(|channel| channel * scale);
+        let (added, removed) = (at(i + radius + 1), at(i - radius));
+        (0..3).for_each(|c| sum[c] += added[c] - removed[c]);
+    }
+}
+
+/// Returns how far the blur of `intensity` in `passes` passes spreads a pixel, the sum of the radii of all its
+/// box blurs
+fn blur_reach(intensity: f32, passes: u32) -> u32 {
+    let radii: usize = box_sizes(intensity / (passes as f32).sqrt())
+        .iter()
+        .map(|size| (size - 1) / 2)
+        .sum();
+    radii as u32 * passes
+}
+
+/// Returns the image as RGB8 with `margin` pixels added on each side, mirroring the pixels next to each edge
+/// without repeating the edge itself (and mirroring again, back and forth, for margins wider than the image)
+fn mirror_pad(image: &DynamicImage, margin: u32) -> DynamicImage {
+    let rgb = image.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let reflect = |i: u32, len: u32| {
+        // reflections repeat every 2 × (len - 1) pixels
+        let period = 2 * (len as i64 - 1);
+        if period == 0 {
+            return 0;
+        }
+        let i = (i as i64 - margin as i64).rem_euclid(period);
+        (if i < len as i64 { i } else { period - i }) as u32
+    };
+    DynamicImage::ImageRgb8(RgbImage::from_fn(
+        width + 2 * margin,
+        height + 2 * margin,
+        |x, y| *rgb.get_pixel(reflect(x, width), reflect(y, height)),
+    ))
+}
+
+/// Returns the image with near-uniform borders trimmed, a border row or column being one whose pixels all
+/// lie within `threshold` (per channel) of the top-left pixel's color
+fn autocrop(image: &DynamicImage, threshold: u8) -> DynamicImage {
+    let rgb = image.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let reference = rgb.get_pixel(0, 0).0;
+    let is_uniform = |x: u32, y: u32| {
+        rgb.get_pixel(x, y)
+            .0
+            .iter()
+            .zip(reference)
+            .all(|(channel, reference)| channel.abs_diff(reference) <= threshold)
+    };
+    let row_is_uniform = |y: u32| (0..width).all(|x| is_uniform(x, y));
+    let mut top = 0;
+    while top < height && row_is_uniform(top) {
+        top += 1;
+    }
+    if top == height {
+        // the whole image is uniform, there is nothing to trim towards
+        return image.clone();
+    }
+    let mut bottom = height;
+    while row_is_uniform(bottom - 1) {
+        bottom -= 1;
+    }
+    let column_is_uniform = |x: u32| (top..bottom).all(|y| is_uniform(x, y));
+    let mut left = 0;
+    while column_is_uniform(left) {
+        left += 1;
+    }
+    let mut right = width;
+    while column_is_uniform(right - 1) {
+        right -= 1;
+    }
+    image.crop_imm(left, top, right - left, bottom - top)
+}
+
+/// Returns the range of `len` rows or columns that is left after removing bars from both ends, where
+/// `is_bar` tells whether a row or column belongs to a bar; bars are only recognized if they are on both ends and of
+/// about the same size, as they are when the content is centered
+fn bar_bounds(len: u32, is_bar: impl Fn(u32) -> bool) -> Option<(u32, u32)> {
+    let start = (0..len).take_while(|&i| is_bar(i)).count() as u32;
+    if start == 0 || start == len {
+        return None;
+    }
+    let end = len - (0..len).rev().take_while(|&i| is_bar(i)).count() as u32;
+    if end == len || start.abs_diff(len - end) > max(2, len / 20) {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// Returns the image with letterbox (top and bottom) or pillarbox (left and right) bars cropped off, where a
+/// bar is made of lines whose neighbouring pixels barely differ, as in a uniform or blurred fill
+fn strip_existing_frame(image: &DynamicImage) -> DynamicImage {
+    let rgb = image.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    if width < 2 || height < 2 {
+        return image.clone();
+    }
+    let difference = |a: &Rgb<u8>, b: &Rgb<u8>| -> u32 {
+        a.0.iter()
+            .zip(b.0)
+            .map(|(a, b)| u32::from(a.abs_diff(b)))
+            .sum()
+    };
+    let is_smooth_row = |y: u32| {
+        let total: u32 = (1..width)
+            .map(|x| difference(rgb.get_pixel(x - 1, y), rgb.get_pixel(x, y)))
+            .sum();
+        total as f32 / ((width - 1) * 3) as f32 <= BAR_ROUGHNESS
+    };
+    let is_smooth_column = |x: u32| {
+        let total: u32 = (1..height)
+            .map(|y| difference(rgb.get_pixel(x, y - 1), rgb.get_pixel(x, y)))
+            .sum();
+        total as f32 / ((height - 1) * 3) as f32 <= BAR_ROUGHNESS
+    };
+    if let Some((top, bottom)) = bar_bounds(height, is_smooth_row) {
+        image.crop_imm(0, top, width, bottom - top)
+    } else if let Some((left, right)) = bar_bounds(width, is_smooth_column) {
+        image.crop_imm(left, 0, right - left, height)
+    } else {
+        image.clone()
+    }
+}
+
+/// Returns the mean relative luminance of the image's pixels at `points`
+fn mean_luminance(image: &DynamicImage, points: impl Iterator<Item = (u32, u32)>) -> f32 {
+    let (mut sum, mut count) = (0., 0);
+    for (x, y) in points {
+        sum += color::relative_luminance(image.get_pixel(x, y).to_rgb().0);
+        count += 1;
+    }
+    sum / count as f32
+}
+
+/// Returns the lowest WCAG contrast ratio, over the sides of `fg` (placed on `bg` at `position`) that have
+/// background next to them, between the mean relative luminance of the image's outermost row or column on
+/// that side and that of the background's row or column beside it; `None` if `fg` covers `bg`
+fn edge_contrast(bg: &DynamicImage, fg: &DynamicImage, position: (u32, u32)) -> Option<f32> {
+    let (x, y) = position;
+    let (width, height) = (fg.width(), fg.height());
+    let mut ratios = Vec::new();
+    let mut compare = |fg_luminance, bg_luminance| {
+        ratios.push(color::contrast_ratio(fg_luminance, bg_luminance));
+    };
+    if x > 0 {
+        compare(
+            mean_luminance(fg, (0..height).map(|i| (0, i))),
+            mean_luminance(bg, (0..height).map(|i| (x - 1, y + i))),
+        );
+    }
+    if x + width < bg.width() {
+        compare(
+            mean_luminance(fg, (0..height).map(|i| (width - 1, i))),
+            mean_luminance(bg, (0..height).map(|i| (x + width, y + i))),
+        );
+    }
+    if y > 0 {
+        compare(
+            mean_luminance(fg, (0..width).map(|i| (i, 0))),
+            mean_luminance(bg, (0..width).map(|i| (x + i, y - 1))),
+        );
+    }
+    if y + height < bg.height() {
+        compare(
+            mean_luminance(fg, (0..width).map(|i| (i, height - 1))),
+            mean_luminance(bg, (0..width).map(|i| (x + i, y + height))),
+        );
+    }
+    ratios.into_iter().reduce(f32::min)
+}
+
+/// Warns (or, with `strict`, fails) if the contrast between the edges of `fg` and the background next to
+/// them is below `min_contrast`
+fn check_contrast(
+    bg: &DynamicImage,
+    fg: &DynamicImage,
+    position: (u32, u32),
+    min_contrast: f32,
+    strict: bool,
+) -> Result<(), SqframeError> {
+    let Some(contrast) = edge_contrast(bg, fg, position) else {
+        return Ok(());
+    };
+    if contrast >= min_contrast {
+        progress!("Contrast: {contrast:.2}");
+        return Ok(());
+    }
+    let message = format!(
+        "the image's edges contrast with the background at {contrast:.2}:1, below {min_contrast}:1"
+    );
+    if strict {
+        return Err(SqframeError::LowContrast(message));
+    }
+    eprintln!("WARNING: {message}");
+    Ok(())
+}
+
+/// Copies `fg` onto `bg` at `position` a whole row at a time, assuming that it fits there
+fn copy_rows<P: Pixel<Subpixel = u8>>(
+    bg: &mut ImageBuffer<P, Vec<u8>>,
+    fg: &ImageBuffer<P, Vec<u8>>,
+    position: (u32, u32),
+) {
+    let channels = P::CHANNEL_COUNT as usize;
+    let (row, stride) = (
+        fg.width() as usize * channels,
+        bg.width() as usize * channels,
+    );
+    if row == 0 {
+        return;
+    }
+    let (x, y) = (position.0 as usize, position.1 as usize);
+    let bg: &mut [u8] = bg;
+    for (i, fg_row) in fg.chunks_exact(row).enumerate() {
+        let start = (y + i) * stride + x * channels;
+        bg[start..start + row].copy_from_slice(fg_row);
+    }
+}
+
+/// Returns an image with `fg` overlaid on `bg` at `position`; if `fg` does not fit into `bg` there, this is an
+/// error with `Fit::Error`, and with `Fit::Contain` `fg` is shrunk to fit and centered (or, if only its position
+/// is off, moved inside) instead. With `keep_alpha`, the result is RGBA and keeps the transparency of `fg`. The
+/// pixels of `fg` are copied verbatim when it already has the result's 8-bit RGB(A) layout, and converted once
+/// otherwise
+fn overlay(
+    bg: &DynamicImage,
+    fg: &DynamicImage,
+    position: (u32, u32),
+    keep_alpha: bool,
+    fit: Fit,
+) -> Result<DynamicImage, SqframeError> {
+    let contained;
+    let (fg, position) = match check_fits(bg.dimensions(), fg.dimensions(), position) {
+        Ok(()) => (fg, position),
+        Err(_) if fit == Fit::Contain && fg.width() <= bg.width() && fg.height() <= bg.height() => {
+            // only the position is off, so the image is moved back inside rather than shrunk
+            let (x, y) = position;
+            (
+                fg,
+                (
+                    min(x, bg.width() - fg.width()),
+                    min(y, bg.height() - fg.height()),
+                ),
+            )
+        }
+        Err(_) if fit == Fit::Contain => {
+            contained = fg.resize(bg.width(), bg.height(), FilterType::Lanczos3);
+            progress!(
+                "Shrunk the {}x{} image to {}x{} to fit the background",
+                fg.width(),
+                fg.height(),
+                contained.width(),
+                contained.height()
+            );
+            let position =
+                placement_offsets(bg.dimensions(), contained.dimensions(), Align::Center);
+            (&contained, position)
+        }
+        Err(e) => return Err(e),
+    };
+    if keep_alpha {
+        let mut final_image = bg.to_rgba8();
+        match fg.as_rgba8() {
+            Some(fg) => copy_rows(&mut final_image, fg, position),
+            None => copy_rows(&mut final_image, &fg.to_rgba8(), position),
+        }
+        return Ok(DynamicImage::ImageRgba8(final_image));
+    }
+    let mut final_image = bg.to_rgb8();
+    match fg.as_rgb8() {
+        Some(fg) => copy_rows(&mut final_image, fg, position),
+        None => copy_rows(&mut final_image, &fg.to_rgb8(), position),
+    }
+    Ok(DynamicImage::ImageRgb8(final_image))
+}
+
+/// Returns an error if an image of size `fg` placed at `position` would extend past one of size `bg` (both as
+/// (width, height))
+fn check_fits(bg: (u32, u32), fg: (u32, u32), position: (u32, u32)) -> Result<(), SqframeError> {
+    let (x, y) = position;
+    if x as u64 + fg.0 as u64 > bg.0 as u64 || y as u64 + fg.1 as u64 > bg.1 as u64 {
+        return Err(SqframeError::Dimension(format!(
+            "the {}x{} image does not fit into the {}x{} background at ({x}, {y})",
+            fg.0, fg.1, bg.0, bg.1
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the image composited over a solid `color`, so that its transparent areas show that color
+fn flatten(image: &DynamicImage, color: [u8; 3]) -> DynamicImage {
+    let rgba = image.to_rgba8();
+    let flattened = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let alpha = a as f32 / 255.;
+        let blend = |fg: u8, pad: u8| (fg as f32 * alpha + pad as f32 * (1. - alpha)).round() as u8;
+        Rgb([blend(r, color[0]), blend(g, color[1]), blend(b, color[2])])
+    });
+    DynamicImage::ImageRgb8(flattened)
+}
+
+/// Returns the image with the watermark alpha-blended into one of its corners, downscaled first if it
+/// would cover more than a quarter of the image's width or height
+fn watermark(image: &DynamicImage, mark: &Watermark) -> DynamicImage {
+    let mut final_image = image.to_rgba8();
+    let (width, height) = final_image.dimensions();
+    let max_side = max(min(width, height) / 4, 1);
+    let mark_image = if mark.image.width() > max_side || mark.image.height() > max_side {
+        mark.image
+            .resize(max_side, max_side, FilterType::Lanczos3)
+            .to_rgba8()
+    } else {
+        mark.image.to_rgba8()
+    };
+    let margin = min(width, height) / 50;
+    let (mark_width, mark_height) = mark_image.dimensions();
+    let align = match mark.position {
+        Corner::TopLeft => Align::TopLeft,
+        Corner::TopRight => Align::TopRight,
+        Corner::BottomLeft => Align::BottomLeft,
+        Corner::BottomRight => Align::BottomRight,
+    };
+    // place the watermark within the image inset by the margin on every side
+    let (x, y) = placement_offsets(
+        (
+            width.saturating_sub(2 * margin),
+            height.saturating_sub(2 * margin),
+        ),
+        (mark_width, mark_height),
+        align,
+    );
+    let (x, y) = (x + margin, y + margin);
+    let opacity = mark.opacity as f32 / 100.;
+    for (mark_x, mark_y, px) in mark_image.enumerate_pixels() {
+        let (final_x, final_y) = (x + mark_x, y + mark_y);
+        if final_x >= width || final_y >= height {
+            continue;
+        }
+        let alpha = px.0[3] as f32 / 255. * opacity;
+        let under = final_image.get_pixel_mut(final_x, final_y);
+        let under_alpha = under.0[3] as f32 / 255.;
+        let out_alpha = alpha + under_alpha * (1. - alpha);
+        if out_alpha > 0. {
+            for channel in 0..3 {
+                under.0[channel] = ((px.0[channel] as f32 * alpha
+                    + under.0[channel] as f32 * under_alpha * (1. - alpha))
+                    / out_alpha)
+                    .round() as u8;
+            }
+        }
+        under.0[3] = (out_alpha * 255.).round() as u8;
+    }
+    if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(final_image)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(final_image).into_rgb8())
+    }
+}
+
+/// A palette of at most 256 colors, quantized from an image with NeuQuant
+struct QuantizedPalette(NeuQuant);
+
+impl ColorMap for QuantizedPalette {
+    type Color = Rgba<u8>;
+
+    fn index_of(&self, color: &Rgba<u8>) -> usize {
+        self.0.index_of(&color.0)
+    }
+
+    fn map_color(&self, color: &mut Rgba<u8>) {
+        self.0.map_pixel(&mut color.0)
+    }
+}
+
+/// Returns a copy of the image reduced to a 256-color palette with Floyd–Steinberg dithering
+fn dither(image: &DynamicImage) -> DynamicImage {
+    let mut rgba = image.to_rgba8();
+    let palette = QuantizedPalette(NeuQuant::new(10, 256, rgba.as_raw()));
+    imageops::dither(&mut rgba, &palette);
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Returns an 8-bit copy of a 16-bit image (with an alpha channel if `has_alpha`), spreading the rounding of its
+/// color channels over a 4×4 Bayer matrix, so that smooth gradients do not band; alpha is rounded
+fn dither_to_8_bit(image: &DynamicImage, has_alpha: bool) -> DynamicImage {
+    const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+    let level = |value: u16, x: u32, y: u32| {
+        let threshold = (BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.;
+        (value as f32 / 257. + threshold).floor().min(255.) as u8
+    };
+    let rgba = image.to_rgba16();
+    let dithered = RgbaImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        Rgba([
+            level(r, x, y),
+            level(g, x, y),
+            level(b, x, y),
+            (a as f32 / 257.).round() as u8,
+        ])
+    });
+    let dithered = DynamicImage::ImageRgba8(dithered);
+    if has_alpha {
+        dithered
+    } else {
+        DynamicImage::ImageRgb8(dithered.to_rgb8())
+    }
+}
+
+/// Returns a hyphen (`"-"`) followed by the current timestamp in milliseconds if successful, otherwise an empty string
+fn get_timestamp_suffix() -> String {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => format!("-{}", duration.as_millis()),
+        Err(_) => String::from(""),
+    }
+}
+
+enum ConfirmResult {
+    Continue,
+    Stop,
+    IOError(io::Error),
+}
+
+/// Prompts the user with a message, expecting "yes", or "no" (or their equivalents in `lang`) and returns a
+/// `ConfirmResult`, an empty answer (or the end of stdin) picks `default`
+fn confirm(msg: String, default: bool, lang: Lang) -> ConfirmResult {
+    let hint = lang.hint(default);
+    let mut stdout = io::stdout();
+    let stdin = io::stdin();
+    let mut resp = String::new();
+    loop {
+        resp.clear();
+        if PROGRESS_TO_STDERR.load(Ordering::Relaxed) {
+            eprint!("{msg} {hint}: ");
+        } else {
+            print!("{msg} {hint}: ");
+            _ = stdout.flush();
+        }
+        match stdin.read_line(&mut resp) {
+            Ok(_) => {}
+            Err(e) => return ConfirmResult::IOError(e),
+        };
+        resp = resp.trim().to_lowercase();
+        if resp.is_empty() {
+            return if default {
+                ConfirmResult::Continue
+            } else {
+                ConfirmResult::Stop
+            };
+        }
+        if lang.is_yes(&resp) {
+            return ConfirmResult::Continue;
+        }
+        if lang.is_no(&resp) {
+            return ConfirmResult::Stop;
+        }
+    }
+}
+
+/// Decodes frame `index` of an animated GIF, or the first frame (noting if there are more) if `None`, and
+/// fails if the animation has no such frame
+fn decode_gif_frame(
+    reader: ImageReader<impl BufRead + Seek>,
+    index: Option<usize>,
+) -> Result<DynamicImage, SqframeError> {
+    let mut frames = GifDecoder::new(reader.into_inner())
+        .map_err(SqframeError::Decode)?
+        .into_frames();
+    let frame = frames
+        .nth(index.unwrap_or(0))
+        .transpose()
+        .map_err(SqframeError::Decode)?;
+    let Some(frame) = frame else {
+        return Err(SqframeError::InvalidArgs(format!(
+            "frame {} does not exist, the animation has fewer frames",
+            index.unwrap_or(0)
+        )));
+    };
+    if index.is_none() && frames.next().is_some() {
+        progress!("Input is animated, only its first frame is used (pick another with '--frame')");
+    }
+    Ok(DynamicImage::ImageRgba8(frame.into_buffer()))
+}
+
+/// Opens and decodes an image as `format`, or as the format detected from its contents if `None`, picking
+/// frame `frame` of an animated GIF
+///
+/// The file is memory-mapped and decoded straight from the mapping, so its encoded bytes live in the page
+/// cache, which the OS can drop and re-read under memory pressure, rather than being copied through a read
+/// buffer; a huge TIFF then only costs the decoded pixels in memory. Files that cannot be mapped, like pipes,
+/// are read into memory instead.
+fn open_image_from_path(
+    input_path: &str,
+    format: Option<ImageFormat>,
+    frame: Option<usize>,
+) -> Result<DynamicImage, SqframeError> {
+    let context = format!("could not open image {input_path:?}");
+    let mut file = File::open(input_path).map_err(SqframeError::io(context.clone()))?;
+    let (mapped, read);
+    // SAFETY: the mapping is only read, while decoding; if another process truncated the file meanwhile, reading
+    // past its new end would raise SIGBUS, the usual risk of mapping a file that is not locked
+    let bytes: &[u8] = match unsafe { Mmap::map(&file) } {
+        Ok(mmap) => {
+            mapped = mmap;
+            &mapped
+        }
+        Err(_) => {
+            read = {
+                let mut buffer = Vec::new();
+                file.read_to_end(&mut buffer)
+                    .map_err(SqframeError::io(context.clone()))?;
+                buffer
+            };
+            &read
+        }
+    };
+    let reader = ImageReader::new(Cursor::new(bytes));
+    let opened = match format {
+        Some(format) => ImageReader::with_format(reader.into_inner(), format),
+        None => reader
+            .with_guessed_format()
+            .map_err(SqframeError::io(context))?,
+    };
+    progress!("Opened image from {input_path:?}");
+    let image = match opened.format() {
+        Some(ImageFormat::Gif) => decode_gif_frame(opened, frame)?,
+        _ if frame.is_some() => {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "'--frame' is only supported for GIF input",
+            )))
+        }
+        _ => opened.decode().map_err(SqframeError::Decode)?,
+    };
+    progress!("Decoded image");
+    Ok(image)
+}
+
+/// Decodes an image from base64 `data` (or stdin if it is "-"), which may be a data URI and contain whitespace
+fn open_image_from_base64(data: &str) -> Result<DynamicImage, SqframeError> {
+    let mut text = Cow::Borrowed(data);
+    if data == "-" {
+        let mut stdin = String::new();
+        io::stdin()
+            .read_to_string(&mut stdin)
+            .map_err(SqframeError::io("could not read stdin"))?;
+        text = Cow::Owned(stdin);
+    }
+    let text = text.trim();
+    let encoded = match text.strip_prefix("data:") {
+        Some(uri) => match uri.split_once(',') {
+            Some((header, encoded)) if header.ends_with(";base64") => encoded,
+            _ => {
+                return Err(SqframeError::InvalidArgs(String::from(
+                    "the input data URI is not base64-encoded",
+                )))
+            }
+        },
+        None => text,
+    };
+    let encoded: String = encoded
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    let bytes = BASE64_STANDARD
+        .decode(encoded)
+        .map_err(|e| SqframeError::InvalidArgs(format!("invalid base64 input: {e}")))?;
+    progress!("Read {} bytes of base64 input", bytes.len());
+    let image = image::load_from_memory(&bytes).map_err(SqframeError::Decode)?;
+    progress!("Decoded image");
+    Ok(image)
+}
+
+fn print_info(path: &str) -> Result<(), SqframeError> {
+    let bytes =
+        fs::read(path).map_err(SqframeError::io(format!("could not open image {path:?}")))?;
+    let reader = ImageReader::new(Cursor::new(&bytes))
+        .with_guessed_format()
+        .map_err(SqframeError::io(format!("could not open image {path:?}")))?;
+    let Some(format) = reader.format() else {
+        return Err(SqframeError::Decode(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Unknown,
+                UnsupportedErrorKind::Format(ImageFormatHint::Unknown),
+            ),
+        )));
+    };
+    let image = reader.decode().map_err(SqframeError::Decode)?;
+    let color = image.color();
+    let orientation = metadata::read(&bytes)
+        .exif
+        .and_then(|exif| metadata::orientation(&exif));
+    println!("Path:        {path}");
+    println!("Dimensions:  {}x{}", image.width(), image.height());
+    println!("Format:      {format:?}");
+    println!("Color type:  {color:?}");
+    println!(
+        "Bit depth:   {} bits per channel",
+        color.bits_per_pixel() / color.channel_count() as u16
+    );
+    println!(
+        "Alpha:       {}",
+        if color.has_alpha() { "yes" } else { "no" }
+    );
+    match orientation {
+        Some(orientation) => println!("Orientation: {orientation}"),
+        None => println!("Orientation: none"),
+    }
+    // rounded to a tenth, as PNG stores resolutions in whole pixels per meter
+    match metadata::resolution(&bytes)
+        .map(|(x, y)| ((x * 10.).round() / 10., (y * 10.).round() / 10.))
+    {
+        Some((x, y)) if x == y => println!("Resolution:  {x} dpi"),
+        Some((x, y)) => println!("Resolution:  {x}x{y} dpi"),
+        None => println!("Resolution:  none"),
+    }
+    Ok(())
+}
+
+/// How often, and how patiently, clipboard access is retried after a failure
+#[derive(Clone, Copy, Debug)]
+struct Retries {
+    attempts: u32,
+    delay: Duration,
+}
+
+/// Calls `f` until it succeeds or `retries.attempts` calls have failed, waiting between attempts with an
+/// exponential backoff
+fn retry_clipboard<T, E>(retries: Retries, mut f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    let mut delay = retries.delay;
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(_) if attempt < retries.attempts => {
+                progress!("Clipboard access failed, retrying in {delay:?}...");
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Runs `f` on a separate thread, returning `None` if it does not finish within `timeout`
+fn with_timeout<T: Send + 'static>(
+    timeout: Duration,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        _ = sender.send(f());
+    });
+    receiver.recv_timeout(timeout).ok()
+}
+
+/// An image read from the clipboard, or the path of an image file copied in a file manager
+enum ClipboardContent {
+    Image(DynamicImage),
+    File(PathBuf),
+}
+
+/// Decodes `%XX` escapes in a URI path
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Returns the existing file named by the first line of clipboard text, given as a `file://` URI or a plain path
+fn clipboard_file_path(text: &str) -> Option<PathBuf> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let path = match line.strip_prefix("file://") {
+        Some(uri_path) => {
+            // skip the host of file://host/path URIs
+            let uri_path = &uri_path[uri_path.find('/')?..];
+            let path = percent_decode(uri_path);
+            // Windows URIs look like file:///C:/path
+            match path.as_bytes() {
+                [b'/', _, b':', ..] => path[1..].to_string(),
+                _ => path,
+            }
+        }
+        None => line.to_string(),
+    };
+    let path = PathBuf::from(path);
+    path.is_file().then_some(path)
+}
+
+fn read_clipboard_image() -> Result<ClipboardContent, String> {
+    match Clipboard::new() {
+        Ok(mut clipboard) => {
+            progress!("Accessed clipboard");
+            match clipboard.get_image() {
+                Ok(img) => {
+                    progress!("Read clipboard image");
+                    match ImageBuffer::from_raw(
+                        img.width.try_into().unwrap(),
+                        img.height.try_into().unwrap(),
+                        img.bytes.into_owned(),
+                    ) {
+                        Some(img) => {
+                            progress!("Constructed clipboard image");
+                            Ok(ClipboardContent::Image(DynamicImage::ImageRgba8(img)))
+                        }
+                        None => Err(String::from("Could not construct clipboard image")),
+                    }
+                }
+                Err(e) => match clipboard
+                    .get_text()
+                    .ok()
+                    .as_deref()
+                    .and_then(clipboard_file_path)
+                {
+                    Some(path) => Ok(ClipboardContent::File(path)),
+                    None => Err(format!("Could not read clipboard image: {e:?}")),
+                },
+            }
+        }
+        Err(e) => Err(format!("Error accessing clipboard: {e:?}")),
+    }
+}
+
+fn open_image_from_clipboard(
+    timeout: Duration,
+    retries: Retries,
+) -> Result<DynamicImage, SqframeError> {
+    match with_timeout(timeout, move || {
+        retry_clipboard(retries, read_clipboard_image)
+    }) {
+        Some(Ok(ClipboardContent::Image(img))) => Ok(img),
+        Some(Ok(ClipboardContent::File(path))) => {
+            progress!("Clipboard holds the file {:?}", path.display());
+            open_image_from_path(&path.to_string_lossy(), None, None)
+        }
+        Some(Err(msg)) => Err(SqframeError::Clipboard(msg)),
+        None => Err(SqframeError::Clipboard(format!(
+            "Timed out after {:?} waiting for the clipboard",
+            timeout
+        ))),
+    }
+}
+
+fn open_image(
+    input_path: Option<String>,
+    input_format: Option<ImageFormat>,
+    frame: Option<usize>,
+    timeout: Duration,
+    retries: Retries,
+) -> Result<DynamicImage, SqframeError> {
+    match input_path {
+        Some(in_path) => open_image_from_path(&in_path, input_format, frame),
+        None => open_image_from_clipboard(timeout, retries),
+    }
+}
+
+/// Encodes the image according to `opts` and embeds `metadata` if the format supports it
+fn encode_with_metadata(
+    image: &DynamicImage,
+    metadata: &Metadata,
+    opts: &SqframeOptions,
+) -> Result<Vec<u8>, SqframeError> {
+    let encoded = encode_image(image, opts)?;
+    if metadata.is_empty() {
+        return Ok(encoded);
+    }
+    match metadata::embed(&encoded, opts.format, metadata) {
+        Some(bytes) => Ok(bytes),
+        None => {
+            progress!("Metadata can only be retained in PNG and JPEG outputs, saving without it");
+            Ok(encoded)
+        }
+    }
+}
+
+fn save_image_to_path(
+    image: DynamicImage,
+    output_path: &Path,
+    temp_dir: PathBuf,
+    metadata: &Metadata,
+    opts: &SqframeOptions,
+    overwrite: Overwrite,
+) -> Result<Written, SqframeError> {
+    let bytes = encode_with_metadata(&image, metadata, opts)?;
+    let written = write_output(&bytes, output_path, temp_dir, overwrite)?;
+    if written == Written::Saved {
+        progress!("Saved image to {:?}!", output_path.display());
+    }
+    Ok(written)
+}
+
+/// What became of an output that was to be written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Written {
+    /// The output was written
+    Saved,
+    /// An existing file already held the output, so it was left alone ('--skip-unchanged')
+    Unchanged,
+    /// Replacing an existing file or the clipboard content was declined at the prompt
+    Declined,
+}
+
+/// How existing output files and clipboard content are treated
+#[derive(Clone, Copy, Debug)]
+struct Overwrite {
+    /// Leave a file alone if it already holds the output
+    skip_unchanged: bool,
+    /// Refuse to replace any existing file, without prompting
+    no_clobber: bool,
+    /// Replace existing files and clipboard content without prompting
+    force: bool,
+    /// Print the bare path of backups instead of a message
+    print_backup_path: bool,
+    /// Read written files back to check that they hold what was written and, for images, that they decode
+    verify: bool,
+    /// Language of the prompts asking before a file or the clipboard is replaced
+    lang: Lang,
+}
+
+/// Writes `bytes` to `output_path`, creating missing directories and, after confirmation, backing up an
+/// existing file to `temp_dir`, and returns whether it was saved or left alone
+fn write_output(
+    bytes: &[u8],
+    output_path: &Path,
+    temp_dir: PathBuf,
+    overwrite: Overwrite,
+) -> Result<Written, SqframeError> {
+    write_output_with(bytes, output_path, temp_dir, overwrite, |path, bytes| {
+        fs::write(path, bytes)
+    })
+}
+
+/// Like `write_output`, but writes the file with `write`, so that tests can make saving fail after the backup
+fn write_output_with(
+    bytes: &[u8],
+    output_path: &Path,
+    temp_dir: PathBuf,
+    overwrite: Overwrite,
+    write: impl FnOnce(&Path, &[u8]) -> io::Result<()>,
+) -> Result<Written, SqframeError> {
+    if output_path.is_dir() || output_path.is_symlink() {
+        return Err(SqframeError::InvalidArgs(format!(
+            "{:?} is a directory or a symbolic link, cannot proceed",
+            output_path.display()
+        )));
+    }
+    if overwrite.skip_unchanged && fs::read(output_path).is_ok_and(|existing| existing == bytes) {
+        progress!("{:?} is unchanged, not rewriting it", output_path.display());
+        return Ok(Written::Unchanged);
+    }
+    if overwrite.no_clobber && output_path.exists() {
+        return Err(SqframeError::Io {
+            context: format!(
+                "{:?} already exists, not overwriting it ('--no-clobber')",
+                output_path.display()
+            ),
+            source: io::ErrorKind::AlreadyExists.into(),
+        });
+    }
+    if fs::metadata(output_path).is_ok_and(|metadata| metadata.permissions().readonly()) {
+        return Err(SqframeError::Io {
+            context: format!("{:?} is read-only, not replacing it", output_path.display()),
+            source: io::ErrorKind::PermissionDenied.into(),
+        });
+    }
+    let mut backup = None;
+    if output_path.is_file() {
+        let answer = if overwrite.force {
+            ConfirmResult::Continue
+        } else {
+            confirm(
+                overwrite
+                    .lang
+                    .replace_file(&format!("{:?}", output_path.display())),
+                false,
+                overwrite.lang,
+            )
+        };
+        match answer {
+            ConfirmResult::Continue => {
+                let backup_path = temp_dir.join(format!(
+                    "{}{}",
+                    backup_prefix(output_path),
+                    get_timestamp_suffix()
+                ));
+                move_file(output_path, &backup_path).map_err(SqframeError::io(format!(
+                    "could not back up original file at {:?}",
+                    output_path.display()
+                )))?;
+                if overwrite.print_backup_path {
+                    println!("{}", backup_path.display());
+                } else {
+                    progress!(
+                        "Original file at {:?} backed up to: {:?}",
+                        output_path.display(),
+                        backup_path.display()
+                    );
+                }
+                backup = Some(backup_path);
+            }
+            ConfirmResult::Stop => {
+                progress!("Please rerun with a different output path, or without an output path (to copy the result to the clipboard)");
+                return Ok(Written::Declined);
+            }
+            ConfirmResult::IOError(e) => {
+                return Err(SqframeError::io("could not read stdin")(e));
+            }
+        }
+    }
+    // only created once it is settled that the file is written, so that a refusal leaves nothing behind
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(SqframeError::io(format!(
+            "could not create directory {:?}",
+            parent.display()
+        )))?;
+    }
+    let saved = write(output_path, bytes)
+        .map_err(SqframeError::io(format!(
+            "could not save to {:?}",
+            output_path.display()
+        )))
+        .and_then(|_| {
+            if overwrite.verify {
+                verify_output(bytes, output_path)?;
+            }
+            Ok(())
+        });
+    if let (Err(_), Some(backup_path)) = (&saved, backup) {
+        restore_backup(&backup_path, output_path);
+    }
+    saved.map(|_| Written::Saved)
+}
+
+/// Moves a backup back to `output_path` after saving there failed, replacing anything partially written
+fn restore_backup(backup_path: &Path, output_path: &Path) {
+    _ = fs::remove_file(output_path);
+    match move_file(backup_path, output_path) {
+        Ok(()) => progress!(
+            "Saving failed, restored the original file at {:?}",
+            output_path.display()
+        ),
+        Err(e) => progress!(
+            "Saving failed and the original file could not be restored ({e}), it is still at {:?}",
+            backup_path.display()
+        ),
+    }
+}
+
+/// Reads the file at `output_path` back and checks that it holds `bytes` (comparing CRC-32 checksums) and, if
+/// its extension is that of an image format, that it decodes
+fn verify_output(bytes: &[u8], output_path: &Path) -> Result<(), SqframeError> {
+    let invalid = |problem: String| SqframeError::Io {
+        context: format!("verification of {:?} failed", output_path.display()),
+        source: io::Error::new(io::ErrorKind::InvalidData, problem),
+    };
+    let written = fs::read(output_path).map_err(SqframeError::io(format!(
+        "could not read {:?} back to verify it",
+        output_path.display()
+    )))?;
+    let (expected, actual) = (crc32fast::hash(bytes), crc32fast::hash(&written));
+    if written.len() != bytes.len() || actual != expected {
+        return Err(invalid(format!(
+            "it holds {} bytes with CRC-32 {actual:08x}, but {} bytes with CRC-32 {expected:08x} were written",
+            written.len(),
+            bytes.len()
+        )));
+    }
+    if let Ok(format) = ImageFormat::from_path(output_path) {
+        image::load_from_memory_with_format(&written, format)
+            .map_err(|e| invalid(format!("it does not decode: {e}")))?;
+    }
+    progress!("Verified {:?} (CRC-32 {actual:08x})", output_path.display());
+    Ok(())
+}
+
+/// How the output is copied to the clipboard
+#[derive(Clone, Copy, Debug)]
+struct ClipboardOutput {
+    format: ClipboardFormat,
+    /// JPEG quality of compressed formats
+    quality: u8,
+    /// Whether bitmaps are copied as BGRA rather than RGBA
+    bgra: bool,
+    retries: Retries,
+}
+
+/// What is placed on the clipboard: raw RGBA pixels or an HTML image
+enum ClipboardPayload {
+    Bitmap(Vec<u8>),
+    Html(String),
+}
+
+/// Returns the image encoded for pasting as HTML, with the format it was encoded in
+fn encode_for_clipboard(
+    image: &DynamicImage,
+    clipboard_format: ClipboardFormat,
+    quality: u8,
+) -> Result<(Vec<u8>, ImageFormat), SqframeError> {
+    match clipboard_format {
+        ClipboardFormat::Jpeg => {
+            let mut encoded = Vec::new();
+            JpegEncoder::new_with_quality(&mut encoded, quality)
+                .encode_image(&image.to_rgb8())
+                .map(|_| (encoded, ImageFormat::Jpeg))
+                .map_err(SqframeError::Encode)
+        }
+        _ => {
+            let opts = SqframeOptions::default();
+            encode_image(image, &opts).map(|encoded| (encoded, opts.format))
+        }
+    }
+}
+
+/// Returns the image's pixels as 8-bit RGBA, which arboard expects whatever the image's own pixel type, or as
+/// BGRA if `bgra`
+fn clipboard_bitmap(image: &DynamicImage, bgra: bool) -> Vec<u8> {
+    let mut bytes = image.to_rgba8().into_raw();
+    if bgra {
+        bytes.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+    }
+    bytes
+}
+
+/// Where clipboard output goes, together with the prompt asking before the clipboard is replaced, so that tests
+/// can stand in for the system clipboard and stdin
+trait ClipboardTarget {
+    /// Asks whether the clipboard's content may be replaced
+    fn confirm_replace(&mut self, lang: Lang) -> ConfirmResult;
+
+    /// Places `payload`, an image of `width`×`height` pixels, on the clipboard
+    fn copy(&mut self, payload: &ClipboardPayload, width: u32, height: u32) -> Result<(), String>;
+}
+
+/// The system clipboard, replaced after asking on stdin
+struct SystemClipboard;
+
+impl ClipboardTarget for SystemClipboard {
+    fn confirm_replace(&mut self, lang: Lang) -> ConfirmResult {
+        confirm(String::from(lang.replace_clipboard()), true, lang)
+    }
+
+    fn copy(&mut self, payload: &ClipboardPayload, width: u32, height: u32) -> Result<(), String> {
+        let mut clipboard =
+            Clipboard::new().map_err(|e| format!("Could not access clipboard: {e:?}"))?;
+        match payload {
+            ClipboardPayload::Bitmap(bytes) => clipboard.set_image(ImageData {
+                width: width as usize,
+                height: height as usize,
+                bytes: Cow::from(bytes),
+            }),
+            ClipboardPayload::Html(html) => {
+                clipboard.set_html(html.as_str(), Some("Image edited with SqFrame"))
+            }
+        }
+        .map_err(|e| format!("Could not copy edited image to clipboard: {e:?}"))
+    }
+}
+
+/// Copies the image to the system clipboard, asking on stdin first unless `overwrite.force` is set
+fn save_image_to_clipboard(
+    image: DynamicImage,
+    clipboard: ClipboardOutput,
+    overwrite: Overwrite,
+) -> Result<Written, SqframeError> {
+    copy_to_clipboard(image, clipboard, overwrite, &mut SystemClipboard)
+}
+
+/// Copies the image to `target` as `clipboard` describes, asking first unless `overwrite.force` is set, and
+/// returns whether it was copied or replacing the clipboard content was declined
+fn copy_to_clipboard(
+    image: DynamicImage,
+    clipboard: ClipboardOutput,
+    overwrite: Overwrite,
+    target: &mut impl ClipboardTarget,
+) -> Result<Written, SqframeError> {
+    let bitmap_bytes = image.width() as u64 * image.height() as u64 * 4;
+    let clipboard_format = match clipboard.format {
+        ClipboardFormat::Bitmap if bitmap_bytes > MAX_CLIPBOARD_BITMAP_BYTES => {
+            progress!(
+                "Edited image is too large for a bitmap ({} MiB), copying a compressed preview instead",
+                bitmap_bytes >> 20
+            );
+            if image.color().has_alpha() {
+                ClipboardFormat::Png
+            } else {
+                ClipboardFormat::Jpeg
+            }
+        }
+        clipboard_format => clipboard_format,
+    };
+    let answer = if overwrite.force {
+        ConfirmResult::Continue
+    } else {
+        target.confirm_replace(overwrite.lang)
+    };
+    match answer {
+        ConfirmResult::Continue => {
+            let contents = match clipboard_format {
+                ClipboardFormat::Bitmap => {
+                    ClipboardPayload::Bitmap(clipboard_bitmap(&image, clipboard.bgra))
+                }
+                ClipboardFormat::Png | ClipboardFormat::Jpeg => {
+                    let (encoded, format) =
+                        encode_for_clipboard(&image, clipboard_format, clipboard.quality)?;
+                    ClipboardPayload::Html(format!("<img src=\"{}\">", data_uri(&encoded, format)))
+                }
+            };
+            let copied = retry_clipboard(clipboard.retries, || {
+                target.copy(&contents, image.width(), image.height())
+            });
+            copied.map_err(SqframeError::Clipboard)?;
+            progress!("Edited image copied to clipboard!");
+            Ok(Written::Saved)
+        }
+        ConfirmResult::Stop => {
+            progress!("Please rerun with the clipboard content backed up, or with an output path specified (see '--help')");
+            Ok(Written::Declined)
+        }
+        ConfirmResult::IOError(e) => Err(SqframeError::io("could not read stdin")(e)),
+    }
+}
+
+/// Returns a base64 data URI of an encoded image
+fn data_uri(encoded: &[u8], format: ImageFormat) -> String {
+    format!(
+        "data:{};base64,{}",
+        format.to_mime_type(),
+        BASE64_STANDARD.encode(encoded)
+    )
+}
+
+/// Prints the encoded image as a base64 data URI on its own line
+fn print_data_uri(
+    image: &DynamicImage,
+    metadata: &Metadata,
+    opts: &SqframeOptions,
+) -> Result<(), SqframeError> {
+    let bytes = encode_with_metadata(image, metadata, opts)?;
+    println!("{}", data_uri(&bytes, opts.format));
+    Ok(())
+}
+
+fn save_image(
+    image: DynamicImage,
+    output_path: Option<String>,
+    metadata: &Metadata,
+    opts: &SqframeOptions,
+    clipboard: ClipboardOutput,
+    overwrite: Overwrite,
+) -> Result<Written, SqframeError> {
+    let temp_dir = backup_dir();
+    match output_path {
+        Some(out_path) => save_image_to_path(
+            image,
+            Path::new(&out_path),
+            temp_dir,
+            metadata,
+            opts,
+            overwrite,
+        ),
+        None => save_image_to_clipboard(image, clipboard, overwrite),
+    }
+}
+
+/// Reads the EXIF and ICC metadata of the input file, or returns empty metadata if stripping is requested
+fn read_metadata(
+    input_path: &Option<String>,
+    keep_metadata: bool,
+) -> Result<Metadata, SqframeError> {
+    match input_path {
+        Some(in_path) if keep_metadata => fs::read(in_path)
+            .map(|bytes| metadata::read(&bytes))
+            .map_err(SqframeError::io(format!(
+                "could not read metadata from {in_path:?}"
+            ))),
+        _ => Ok(Metadata::default()),
+    }
+}
+
+/// A step of framing an image, as reported to progress callbacks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// The input was decoded
+    Decode,
+    /// The image was upscaled to cover the square, for a blurred background
+    Upscale,
+    /// The upscaled image was cropped to the square
+    Crop,
+    /// The background was blurred
+    Blur,
+    /// The image was placed on its background
+    Overlay,
+    /// The output was encoded
+    Encode,
+}
+
+impl Stage {
+    /// Returns roughly how much of the whole process (as a percentage) is done once this stage is
+    fn percent(self) -> f32 {
+        match self {
+            Stage::Decode => 10.,
+            Stage::Upscale => 25.,
+            Stage::Crop => 30.,
+            Stage::Blur => 70.,
+            Stage::Overlay => 85.,
+            Stage::Encode => 100.,
+        }
+    }
+}
+
+/// Returns the image framed in a square with a blurred background, according to `opts`
+pub fn square_image(
+    image: &DynamicImage,
+    opts: &SqframeOptions,
+) -> Result<DynamicImage, SqframeError> {
+    square_image_with_progress(image, opts, &|_, _| {})
+}
+
+/// Like `square_image`, but calls `on_progress` with each stage and the percentage of the whole process done
+/// once it is complete; stages that do not apply to `opts` (such as blurring a palette background) are skipped
+pub fn square_image_with_progress(
+    image: &DynamicImage,
+    opts: &SqframeOptions,
+    on_progress: &dyn Fn(Stage, f32),
+) -> Result<DynamicImage, SqframeError> {
+    let resamples = opts.scale.is_some()
+        || opts.autocrop.is_some()
+        || opts.detect_frame
+        || opts.supersample > 1
+        || !matches!(opts.square_size, SquareSize::Max);
+    if opts.only_pad && resamples {
+        return Err(SqframeError::InvalidArgs(String::from(
+            "only padding was requested, but the options would crop or resize the image",
+        )));
+    }
+    let input_size = image.dimensions();
+    if let Some(roi) = opts.roi {
+        if !matches!(opts.rotate, Rotation::None)
+            || opts.flip.is_some()
+            || opts.detect_frame
+            || opts.autocrop.is_some()
+        {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "a region of interest cannot be combined with rotating, flipping or trimming the image",
+            )));
+        }
+        if roi.x as u64 + roi.width as u64 > input_size.0 as u64
+            || roi.y as u64 + roi.height as u64 > input_size.1 as u64
+        {
+            return Err(SqframeError::InvalidArgs(format!(
+                "the region {}x{} at ({}, {}) extends past the {}x{} image",
+                roi.width, roi.height, roi.x, roi.y, input_size.0, input_size.1
+            )));
+        }
+    }
+    let image = match opts.rotate {
+        Rotation::None => Cow::Borrowed(image),
+        Rotation::Quarter => Cow::Owned(image.rotate90()),
+        Rotation::Half => Cow::Owned(image.rotate180()),
+        Rotation::ThreeQuarters => Cow::Owned(image.rotate270()),
+    };
+    if let Cow::Owned(rotated) = &image {
+        progress!("Rotate: done ({}x{})", rotated.width(), rotated.height());
+    }
+    let image = match opts.flip {
+        Some(flip) => {
+            let flipped = flip.apply(&image);
+            progress!("Flip: done");
+            Cow::Owned(flipped)
+        }
+        None => image,
+    };
+    let image = if opts.detect_frame {
+        let stripped = strip_existing_frame(&image);
+        progress!(
+            "Existing frame detection: done ({}x{})",
+            stripped.width(),
+            stripped.height()
+        );
+        Cow::Owned(stripped)
+    } else {
+        image
+    };
+    let image = match opts.autocrop {
+        Some(threshold) => {
+            let cropped = autocrop(&image, threshold);
+            progress!("Autocrop: done ({}x{})", cropped.width(), cropped.height());
+            Cow::Owned(cropped)
+        }
+        None => image,
+    };
+    let image = match opts.scale {
+        Some(percent) => {
+            let side = max(image.width(), image.height()) as f32 * percent / 100.;
+            let side = max(side.round() as u32, 1);
+            let scaled = image.resize(side, side, FilterType::Lanczos3);
+            progress!("Scale: done ({}x{})", scaled.width(), scaled.height());
+            Cow::Owned(scaled)
+        }
+        None => image,
+    };
+    let side = opts.square_size.side(image.width(), image.height());
+    let image = match opts.max_dimension {
+        Some(max_side) if side > max_side => {
+            if opts.only_pad {
+                return Err(SqframeError::InvalidArgs(format!(
+                    "only padding was requested, but the {side}px square would be downscaled to {max_side}px"
+                )));
+            }
+            let (width, height) = (image.width() as u64, image.height() as u64);
+            let downscaled = image.resize_exact(
+                max((width * max_side as u64 / side as u64) as u32, 1),
+                max((height * max_side as u64 / side as u64) as u32, 1),
+                FilterType::Lanczos3,
+            );
+            progress!(
+                "Max dimension: done ({}x{})",
+                downscaled.width(),
+                downscaled.height()
+            );
+            Cow::Owned(downscaled)
+        }
+        _ => image,
+    };
+    let supersampled;
+    let (image, opts) = match opts.supersample {
+        1 => (image, opts),
+        factor => {
+            let enlarged_size = image
+                .width()
+                .checked_mul(factor)
+                .zip(image.height().checked_mul(factor))
+                .filter(|&(width, height)| width as u64 * height as u64 <= MAX_BACKGROUND_PIXELS);
+            let Some((width, height)) = enlarged_size else {
+                return Err(SqframeError::Dimension(format!(
+                    "a {}x{} image is too large to supersample {factor} times, lower '--supersample'",
+                    image.width(),
+                    image.height()
+                )));
+            };
+            let enlarged = image.resize_exact(width, height, FilterType::Lanczos3);
+            progress!(
+                "Supersample: done ({}x{})",
+                enlarged.width(),
+                enlarged.height()
+            );
+            supersampled = opts.scaled_by(factor);
+            (Cow::Owned(enlarged), &supersampled)
+        }
+    };
+    // only uniform resizing can have happened since, so the region is scaled along with the image
+    let roi = opts.roi.map(|roi| {
+        let (scale_x, scale_y) = (
+            image.width() as f64 / input_size.0 as f64,
+            image.height() as f64 / input_size.1 as f64,
+        );
+        Roi {
+            x: (roi.x as f64 * scale_x).round() as u32,
+            y: (roi.y as f64 * scale_y).round() as u32,
+            width: max((roi.width as f64 * scale_x).round() as u32, 1),
+            height: max((roi.height as f64 * scale_y).round() as u32, 1),
+        }
+    });
+    let mut final_image = frame(&image, roi, opts, on_progress)?;
+    on_progress(Stage::Overlay, Stage::Overlay.percent());
+    if let Some(mark) = &opts.watermark {
+        final_image = watermark(&final_image, mark);
+        progress!("Watermark: done");
+    }
+    if let Some(label) = &opts.label {
+        final_image = draw_label(&final_image, label);
+        progress!("Label: done");
+    }
+    if let Some(radius) = opts.outer_radius {
+        final_image = round_corners(&final_image, radius);
+        progress!("Rounded corners: done");
+    }
+    if opts.supersample > 1 {
+        final_image = final_image.resize_exact(
+            final_image.width() / opts.supersample,
+            final_image.height() / opts.supersample,
+            // averages like a box filter would, without the ringing that Lanczos adds around hard edges
+            FilterType::Triangle,
+        );
+        progress!(
+            "Downsample: done ({}x{})",
+            final_image.width(),
+            final_image.height()
+        );
+    }
+    progress!("Done!");
+    Ok(final_image)
+}
+
+/// Returns the image with its corners rounded off with `radius` (at most half its shorter side), making the
+/// pixels outside them transparent and antialiasing their edge
+fn round_corners(image: &DynamicImage, radius: u32) -> DynamicImage {
+    let mut final_image = image.to_rgba8();
+    let (width, height) = final_image.dimensions();
+    let radius = min(radius, min(width, height) / 2);
+    let r = radius as f32;
+    for (x, y, pixel) in final_image.enumerate_pixels_mut() {
+        // distance of the pixel's center from the center of its corner's circle, along each axis
+        let dx = (r - x as f32 - 0.5).max(x as f32 + 0.5 - (width - radius) as f32);
+        let dy = (r - y as f32 - 0.5).max(y as f32 + 0.5 - (height - radius) as f32);
+        if dx <= 0. || dy <= 0. {
+            continue;
+        }
+        let coverage = (r - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0., 1.);
+        pixel.0[3] = (pixel.0[3] as f32 * coverage).round() as u8;
+    }
+    DynamicImage::ImageRgba8(final_image)
+}
+
+/// Returns the path an output of `size` pixels per side is saved to for `--sizes`
+fn sized_path(output_path: &str, size: u32) -> PathBuf {
+    if output_path.contains("{size}") {
+        return PathBuf::from(output_path.replace("{size}", &size.to_string()));
+    }
+    let path = Path::new(output_path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{stem}-{size}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{size}"),
+    };
+    path.with_file_name(file_name)
+}
+
+/// Returns `original` (scaled to the height of `framed`) and `framed` next to each other
+fn side_by_side(original: &DynamicImage, framed: &DynamicImage) -> DynamicImage {
+    let height = framed.height();
+    let width = max(
+        1,
+        (original.width() as u64 * height as u64 / original.height() as u64) as u32,
+    );
+    let mut final_image = RgbaImage::new(width + framed.width(), height);
+    imageops::replace(
+        &mut final_image,
+        &original
+            .resize_exact(width, height, FilterType::Lanczos3)
+            .to_rgba8(),
+        0,
+        0,
+    );
+    imageops::replace(&mut final_image, &framed.to_rgba8(), width as i64, 0);
+    if original.color().has_alpha() || framed.color().has_alpha() {
+        DynamicImage::ImageRgba8(final_image)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(final_image).into_rgb8())
+    }
+}
+
+/// Returns the average color of the image's pixels
+fn average_color(image: &DynamicImage) -> [u8; 3] {
+    let colors = get_colors(image);
+    let mut sums = [0u64; 3];
+    for color in &colors {
+        for channel in 0..3 {
+            sums[channel] += color[channel] as u64;
+        }
+    }
+    let count = max(colors.len(), 1) as u64;
+    sums.map(|sum| ((sum + count / 2) / count) as u8)
+}
+
+/// Returns up to `k` dominant colors of the image, most common first, found by k-means clustering of a sample
+/// of its pixels
+fn dominant_colors(image: &DynamicImage, k: usize) -> Vec<[u8; 3]> {
+    // a single cluster's center is the mean of all pixels, which needs no sampling or iterating
+    if k == 1 {
+        return vec![average_color(image)];
+    }
+    let colors = get_colors(image);
+    let step = max(1, colors.len() / MAX_PALETTE_SAMPLES);
+    let samples: Vec<[f32; 3]> = colors
+        .iter()
+        .step_by(step)
+        .map(|color| color.map(|channel| channel as f32))
+        .collect();
+    let k = min(k, samples.len());
+    let mut centers: Vec<[f32; 3]> = (0..k).map(|i| samples[i * samples.len() / k]).collect();
+    let mut counts = vec![0usize; k];
+    for _ in 0..PALETTE_ITERATIONS {
+        let mut sums = vec![[0f32; 3]; k];
+        counts = vec![0; k];
+        for sample in &samples {
+            let distance = |center: &[f32; 3]| -> f32 {
+                (0..3).map(|c| (center[c] - sample[c]).powi(2)).sum()
+            };
+            let nearest = (0..k)
+                .min_by(|a, b| distance(&centers[*a]).total_cmp(&distance(&centers[*b])))
+                .unwrap_or(0);
+            for c in 0..3 {
+                sums[nearest][c] += sample[c];
+            }
+            counts[nearest] += 1;
+        }
+        for i in 0..k {
+            if counts[i] > 0 {
+                centers[i] = sums[i].map(|sum| sum / counts[i] as f32);
+            }
+        }
+    }
+    let mut ranked: Vec<_> = centers.into_iter().zip(counts).collect();
+    ranked.sort_by_key(|(_, count)| Reverse(*count));
+    ranked
+        .into_iter()
+        .map(|(center, _)| center.map(|channel| channel.round() as u8))
+        .collect()
+}
+
+/// Returns a square background with sides of length `side` that smoothly blends the image's `k` dominant colors
+fn palette_background(image: &DynamicImage, k: usize, side: u32) -> DynamicImage {
+    let palette = dominant_colors(image, k);
+    // lay the colors out on a small grid, which upscaling with a linear filter turns into smooth gradients
+    let grid = (palette.len() as f32).sqrt().ceil() as u32;
+    let grid_image = RgbImage::from_fn(grid, grid, |x, y| {
+        Rgb(palette[(y * grid + x) as usize % palette.len()])
+    });
+    DynamicImage::ImageRgb8(grid_image).resize_exact(side, side, FilterType::Triangle)
+}
+
+/// Returns the image upscaled to cover a square with sides of length max(width, height), cropped to it and
+/// blurred according to `opts`
+fn blurred_background(
+    image: &DynamicImage,
+    opts: &SqframeOptions,
+    on_progress: &dyn Fn(Stage, f32),
+) -> Result<DynamicImage, SqframeError> {
+    let (width, height) = (image.width(), image.height());
+    let sqside = max(width, height);
+    let factor = min(width, height);
+    let resized_width = width as u64 * sqside as u64 / factor as u64;
+    let resized_height = height as u64 * sqside as u64 / factor as u64;
+    // with `bg_downscale` or `fast`, the background is built and blurred at a fraction of its size and enlarged
+    // last
+    let downscale = if opts.fast {
+        max(opts.bg_downscale, sqside.div_ceil(FAST_BACKGROUND_SIDE))
+    } else {
+        opts.bg_downscale
+    };
+    let side = max(sqside / downscale, 1);
+    let (full_width, full_height) = (resized_width, resized_height);
+    let resized_width = resized_width * side as u64 / sqside as u64;
+    let resized_height = resized_height * side as u64 / sqside as u64;
+    if resized_width * resized_height > MAX_BACKGROUND_PIXELS {
+        return Err(SqframeError::Dimension(format!(
+            "a {width}x{height} image would need a {full_width}x{full_height} background, build it smaller \
+             with '--bg-downscale' or limit the square with '--max-dimension'"
+        )));
+    }
+    let (resized_width, resized_height) = (resized_width as u32, resized_height as u32);
+    // the blur hides the aliasing of nearest-neighbor sampling
+    let filter = if opts.fast {
+        FilterType::Nearest
+    } else {
+        FilterType::Triangle
+    };
+    let mut bg = image.resize(resized_width, resized_height, filter);
+    progress!("Upscale: done");
+    save_debug(opts, "1-upscaled", &bg);
+    on_progress(Stage::Upscale, Stage::Upscale.percent());
+    let (crop_x, crop_y) = opts
+        .crop_gravity
+        .crop_offsets(resized_width, resized_height, side);
+    bg = bg.crop(crop_x, crop_y, side, side);
+    progress!("Square crop: done");
+    save_debug(opts, "2-cropped", &bg);
+    on_progress(Stage::Crop, Stage::Crop.percent());
+    let mut blur_radius = match opts.adaptive_blur {
+        Some((min_radius, max_radius)) => {
+            let density = edge_density(image);
+            let busyness = (density / BUSY_EDGE_DENSITY).min(1.);
+            let radius = min_radius + (max_radius - min_radius) * busyness;
+            progress!("Adaptive blur radius: {radius:.1} (edge density {density:.3})");
+            radius
+        }
+        None => opts.blur,
+    };
+    if opts.auto_blur {
+        blur_radius *= sqside as f32 / factor as f32;
+        progress!("Auto blur radius: {blur_radius:.1}");
+    }
+    let blur = if opts.linear_blur { blur_linear } else { blur };
+    let blur_radius = blur_radius * side as f32 / sqside as f32;
+    let blurred = if opts.symmetric_blur {
+        let margin = min(blur_reach(blur_radius, opts.blur_passes), side - 1);
+        let padded_side = side as u64 + 2 * margin as u64;
+        if padded_side * padded_side > MAX_BACKGROUND_PIXELS {
+            return Err(SqframeError::Dimension(format!(
+                "a {side}x{side} background mirrored by {margin} pixels on each side is too large to blur, \
+                 build it smaller with '--bg-downscale' or use a smaller blur radius"
+            )));
+        }
+        blur(&mirror_pad(&bg, margin), blur_radius, opts.blur_passes)?
+            .crop_imm(margin, margin, side, side)
+    } else {
+        blur(&bg, blur_radius, opts.blur_passes)?
+    };
+    progress!("Gaussian blur: done");
+    save_debug(opts, "3-blurred", &blurred);
+    on_progress(Stage::Blur, Stage::Blur.percent());
+    bg = if opts.radial_focus {
+        let focused = radial_blend(&bg, &blurred);
+        progress!("Radial focus: done");
+        focused
+    } else {
+        blurred
+    };
+    if bg.width() != sqside {
+        bg = bg.resize_exact(sqside, sqside, FilterType::Triangle);
+        progress!("Enlarge: done");
+    }
+    Ok(bg)
+}
+
+/// Edge density at and above which `--adaptive-blur` uses its maximum radius, that of busy photos such as foliage
+/// or crowds
+const BUSY_EDGE_DENSITY: f32 = 0.15;
+
+/// Returns the mean Sobel gradient magnitude of the image's luma, from 0 for a flat image to 1 for the strongest
+/// possible edges everywhere, measured on a copy of at most 256 pixels per side to keep it cheap
+fn edge_density(image: &DynamicImage) -> f32 {
+    // `thumbnail` would also enlarge smaller images
+    let luma = if image.width() > 256 || image.height() > 256 {
+        image.thumbnail(256, 256).to_luma8()
+    } else {
+        image.to_luma8()
+    };
+    let (width, height) = luma.dimensions();
+    if width < 3 || height < 3 {
+        return 0.;
+    }
+    let at = |x: u32, y: u32| luma.get_pixel(x, y).0[0] as f32;
+    let mut total = 0.;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let gx = at(x + 1, y - 1) + 2. * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2. * at(x - 1, y)
+                - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2. * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2. * at(x, y - 1)
+                - at(x + 1, y - 1);
+            total += (gx * gx + gy * gy).sqrt();
+        }
+    }
+    // a single Sobel kernel responds with at most 4 × 255
+    (total / ((width - 2) * (height - 2)) as f32 / (4. * 255.)).min(1.)
+}
+
+/// Blends the sharp and blurred versions of a square background with a radial mask, keeping it sharp at the
+/// center and fully blurred from the middle of each edge outwards
+fn radial_blend(sharp: &DynamicImage, blurred: &DynamicImage) -> DynamicImage {
+    let (sharp, mut final_image) = (sharp.to_rgb8(), blurred.to_rgb8());
+    let (width, height) = final_image.dimensions();
+    let (center_x, center_y) = (width as f32 / 2., height as f32 / 2.);
+    let radius = center_x.min(center_y).max(1.);
+    for (x, y, px) in final_image.enumerate_pixels_mut() {
+        let distance = (x as f32 + 0.5 - center_x).hypot(y as f32 + 0.5 - center_y);
+        let weight = (distance / radius).min(1.);
+        let sharp_px = sharp.get_pixel(x, y);
+        for channel in 0..3 {
+            px.0[channel] = (px.0[channel] as f32 * weight
+                + sharp_px.0[channel] as f32 * (1. - weight))
+                .round() as u8;
+        }
+    }
+    DynamicImage::ImageRgb8(final_image)
+}
+
+/// Returns the background with `glow` blended in around where an image of `fg_width`x`fg_height` is placed
+/// at `position`
+fn add_glow(
+    bg: &DynamicImage,
+    fg_width: u32,
+    fg_height: u32,
+    position: (u32, u32),
+    glow: Glow,
+) -> Result<DynamicImage, SqframeError> {
+    let (bg_width, bg_height) = (bg.width(), bg.height());
+    let (x, y) = position;
+    let (x_rng, y_rng) = (x..x + fg_width, y..y + fg_height);
+    let mask = RgbImage::from_fn(bg_width, bg_height, |x, y| {
+        if x_rng.contains(&x) && y_rng.contains(&y) {
+            Rgb([255; 3])
+        } else {
+            Rgb([0; 3])
+        }
+    });
+    let mask = blur(&DynamicImage::ImageRgb8(mask), glow.radius, 1)?.into_rgb8();
+    let mut final_image = bg.to_rgb8();
+    for (px, mask_px) in final_image.pixels_mut().zip(mask.pixels()) {
+        let alpha = mask_px.0[0] as f32 / 255.;
+        for channel in 0..3 {
+            px.0[channel] = (glow.color[channel] as f32 * alpha
+                + px.0[channel] as f32 * (1. - alpha))
+                .round() as u8;
+        }
+    }
+    Ok(DynamicImage::ImageRgb8(final_image))
+}
+
+/// Draws a border `border` pixels wide, in the image's dominant color, around where `fg` is placed on `image`
+/// at `position`, clipped to `image`
+fn draw_border(
+    image: &mut DynamicImage,
+    fg: &DynamicImage,
+    position: (u32, u32),
+    border: u32,
+    palette_size: usize,
+) {
+    let Some(&[r, g, b]) = dominant_colors(fg, palette_size).first() else {
+        return;
+    };
+    let (x, y) = position;
+    let (x_rng, y_rng) = (x..x + fg.width(), y..y + fg.height());
+    let (left, top) = (x.saturating_sub(border), y.saturating_sub(border));
+    let right = min(x_rng.end.saturating_add(border), image.width());
+    let bottom = min(y_rng.end.saturating_add(border), image.height());
+    for py in top..bottom {
+        for px in left..right {
+            if !(x_rng.contains(&px) && y_rng.contains(&py)) {
+                image.put_pixel(px, py, Rgba([r, g, b, 255]));
+            }
+        }
+    }
+}
+
+/// Returns the image with `text` drawn in white on a dark box in its bottom-left corner, shortened with "..."
+/// if it does not fit
+fn draw_label(image: &DynamicImage, text: &str) -> DynamicImage {
+    let mut final_image = image.to_rgba8();
+    let (width, height) = final_image.dimensions();
+    let scale = max(min(width, height) / 250, 1);
+    let margin = min(width, height) / 50;
+    let padding = 2 * scale;
+    let advance = (font::GLYPH_WIDTH + 1) * scale;
+    let max_chars = (width.saturating_sub(2 * (margin + padding)) / advance) as usize;
+    let chars: Vec<char> = text.chars().collect();
+    let chars = if chars.len() <= max_chars {
+        chars
+    } else if max_chars > 3 {
+        let mut shortened = chars[..max_chars - 3].to_vec();
+        shortened.extend("...".chars());
+        shortened
+    } else {
+        chars[..max_chars].to_vec()
+    };
+    if chars.is_empty() {
+        return image.clone();
+    }
+    let box_width = chars.len() as u32 * advance - scale + 2 * padding;
+    let box_height = font::GLYPH_HEIGHT * scale + 2 * padding;
+    let (box_x, box_y) = placement_offsets(
+        (
+            width.saturating_sub(2 * margin),
+            height.saturating_sub(2 * margin),
+        ),
+        (box_width, box_height),
+        Align::BottomLeft,
+    );
+    let (box_x, box_y) = (box_x + margin, box_y + margin);
+    for y in box_y..min(box_y + box_height, height) {
+        for x in box_x..min(box_x + box_width, width) {
+            let px = final_image.get_pixel_mut(x, y);
+            for channel in 0..3 {
+                px.0[channel] = (px.0[channel] as f32 * 0.4).round() as u8;
+            }
+            px.0[3] = max(px.0[3], 160);
+        }
+    }
+    for (i, c) in chars.into_iter().enumerate() {
+        let (glyph_x, glyph_y) = (box_x + padding + i as u32 * advance, box_y + padding);
+        for (row, bits) in font::glyph(c).into_iter().enumerate() {
+            for column in 0..font::GLYPH_WIDTH {
+                if bits & (1 << (font::GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+                let (x, y) = (glyph_x + column * scale, glyph_y + row as u32 * scale);
+                for (dx, dy) in (0..scale).flat_map(|dx| (0..scale).map(move |dy| (dx, dy))) {
+                    if x + dx < width && y + dy < height {
+                        final_image.put_pixel(x + dx, y + dy, Rgba([255; 4]));
+                    }
+                }
+            }
+        }
+    }
+    if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(final_image)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(final_image).into_rgb8())
+    }
+}
+
+/// Returns the image placed at `position` on a fully transparent square with sides of length `side`
+fn overlay_transparent(side: u32, fg: &DynamicImage, position: (u32, u32)) -> DynamicImage {
+    let mut final_image = RgbaImage::new(side, side);
+    let (x, y) = position;
+    imageops::replace(&mut final_image, &fg.to_rgba8(), x as i64, y as i64);
+    DynamicImage::ImageRgba8(final_image)
+}
+
+/// Returns the image overlaid on a square blurred background of its own
+fn frame(
+    image: &DynamicImage,
+    roi: Option<Roi>,
+    opts: &SqframeOptions,
+    on_progress: &dyn Fn(Stage, f32),
+) -> Result<DynamicImage, SqframeError> {
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 {
+        return Err(SqframeError::Dimension(String::from(
+            "the image has no pixels",
+        )));
+    }
+    let side = opts.square_size.side(width, height);
+    // checked before anything of the square's size is allocated
+    if side as u64 * side as u64 > MAX_BACKGROUND_PIXELS {
+        return Err(SqframeError::Dimension(format!(
+            "a {width}x{height} image would need a {side}x{side} square, limit its side with '--max-dimension'"
+        )));
+    }
+    let mut focus = roi.map(Roi::center);
+    let cropped;
+    let (image, width, height) = if side < max(width, height) {
+        let (crop_width, crop_height) = (min(width, side), min(height, side));
+        let (x, y) = match (roi, focus) {
+            (Some(roi), Some((center_x, center_y))) => {
+                if roi.width > crop_width || roi.height > crop_height {
+                    eprintln!(
+                        "WARNING: the {}x{} region of interest does not fit in the {crop_width}x{crop_height} crop, its edges are cut off",
+                        roi.width, roi.height
+                    );
+                }
+                // the crop window is centered on the region, as far as the image's edges allow
+                let start = |center: f64, len: u32, window: u32| {
+                    (center - window as f64 / 2.)
+                        .round()
+                        .clamp(0., (len - window) as f64) as u32
+                };
+                (
+                    start(center_x, width, crop_width),
+                    start(center_y, height, crop_height),
+                )
+            }
+            _ => placement_offsets((width, height), (crop_width, crop_height), Align::Center),
+        };
+        focus = focus.map(|(center_x, center_y)| (center_x - x as f64, center_y - y as f64));
+        cropped = image.crop_imm(x, y, crop_width, crop_height);
+        progress!("Cropped image to {crop_width}x{crop_height}");
+        (&cropped, crop_width, crop_height)
+    } else {
+        (image, width, height)
+    };
+    // only the foreground is flattened, the background is made from the image as it is
+    let padded;
+    let fg = match opts.pad_color {
+        Some(color) => {
+            padded = flatten(image, color);
+            progress!("Pad color: done");
+            &padded
+        }
+        None => image,
+    };
+    save_debug(opts, "0-foreground", fg);
+    if width == height && !opts.always_frame {
+        // overlaying a square image onto a background of the same size leaves just the image
+        progress!("Image is already square, no framing needed");
+        return Ok(match (opts.background, opts.channels) {
+            (Background::None, _) | (_, Some(Channels::Rgba)) => {
+                DynamicImage::ImageRgba8(fg.to_rgba8())
+            }
+            _ => DynamicImage::ImageRgb8(fg.to_rgb8()),
+        });
+    }
+    let side = max(width, height);
+    let position = match focus {
+        // the image is moved so that the region's center lands on the square's, as far as the square allows
+        Some((center_x, center_y)) => {
+            let start = |center: f64, len: u32| {
+                (side as f64 / 2. - center)
+                    .round()
+                    .clamp(0., (side - len) as f64) as u32
+            };
+            (start(center_x, width), start(center_y, height))
+        }
+        None => biased_offsets((side, side), (width, height), opts.offset),
+    };
+    if let Background::None = opts.background {
+        progress!("Constructing final image...");
+        let mut final_image = overlay_transparent(side, fg, position);
+        if let Some(border) = opts.border {
+            draw_border(&mut final_image, image, position, border, opts.palette_size);
+            progress!("Border: done");
+        }
+        save_debug(opts, "5-overlaid", &final_image);
+        return Ok(final_image);
+    }
+    // another image is first fitted into the square, so that the blur radius means the same as for the input
+    let bg_source = match &opts.bg_source {
+        Some(source) => Cow::Owned(source.resize(side, side, FilterType::Triangle)),
+        None => Cow::Borrowed(image),
+    };
+    let mut bg = match opts.background {
+        Background::Palette => {
+            progress!("Creating palette background...");
+            let bg = palette_background(&bg_source, opts.palette_size, side);
+            progress!("Palette: done");
+            bg
+        }
+        Background::Average => {
+            progress!("Creating average color background...");
+            let bg = RgbImage::from_pixel(side, side, Rgb(average_color(&bg_source)));
+            progress!("Average color: done");
+            DynamicImage::ImageRgb8(bg)
+        }
+        _ => {
+            progress!("Creating blurred background...");
+            blurred_background(&bg_source, opts, on_progress)?
+        }
+    };
+    if opts.bg_grayscale {
+        bg = DynamicImage::ImageLuma8(imageops::grayscale(&bg))
+            .into_rgb8()
+            .into();
+        progress!("Grayscale: done");
+    }
+    if let Some(glow) = opts.glow {
+        bg = add_glow(&bg, width, height, position, glow)?;
+        progress!("Glow: done");
+    }
+    if let Some(border) = opts.border {
+        draw_border(&mut bg, image, position, border, opts.palette_size);
+        progress!("Border: done");
+    }
+    progress!("Background created");
+    save_debug(opts, "4-background", &bg);
+    if let Some(min_contrast) = opts.min_contrast {
+        check_contrast(&bg, fg, position, min_contrast, opts.strict_contrast)?;
+    }
+    progress!("Constructing final image...");
+    let final_image = overlay(
+        &bg,
+        fg,
+        position,
+        opts.channels == Some(Channels::Rgba),
+        opts.fit,
+    )?;
+    save_debug(opts, "5-overlaid", &final_image);
+    Ok(final_image)
+}
+
+/// Writes an intermediate image to `<name>.png` in the debug directory of `opts`, if it has one; failing to is
+/// only warned about, since the debug images are not the output
+fn save_debug(opts: &SqframeOptions, name: &str, image: &DynamicImage) {
+    let Some(dir) = &opts.debug_dir else {
+        return;
+    };
+    let path = dir.join(format!("{name}.png"));
+    let saved = fs::create_dir_all(dir).and_then(|_| {
+        image
+            .save_with_format(&path, ImageFormat::Png)
+            .map_err(io::Error::other)
+    });
+    match saved {
+        Ok(()) => progress!("Debug image: {:?}", path.display()),
+        Err(e) => eprintln!(
+            "WARNING: could not write debug image {:?}: {e}",
+            path.display()
+        ),
+    }
+}
+
+/// Encodes the image in the format given by `opts`, applying its output options
+pub fn encode_image(image: &DynamicImage, opts: &SqframeOptions) -> Result<Vec<u8>, SqframeError> {
+    opts.validate()?;
+    let color = image.color();
+    let has_alpha = match opts.channels {
+        Some(channels) => channels == Channels::Rgba,
+        None => color.has_alpha(),
+    };
+    let sixteen_bit = color.bytes_per_pixel() > color.channel_count();
+    let bit_depth = match opts.bit_depth {
+        Some(bit_depth) => bit_depth,
+        None if opts.channels.is_none() => return encode(image, opts, opts.dither),
+        None if sixteen_bit => BitDepth::Sixteen,
+        None => BitDepth::Eight,
+    };
+    let image = match bit_depth {
+        BitDepth::Eight if sixteen_bit && opts.dither => {
+            let dithered = dither_to_8_bit(image, has_alpha);
+            progress!("Dithering to 8 bits: done");
+            return encode(&dithered, opts, false);
+        }
+        BitDepth::Eight if has_alpha => DynamicImage::ImageRgba8(image.to_rgba8()),
+        BitDepth::Eight => DynamicImage::ImageRgb8(image.to_rgb8()),
+        BitDepth::Sixteen if has_alpha => DynamicImage::ImageRgba16(image.to_rgba16()),
+        BitDepth::Sixteen => DynamicImage::ImageRgb16(image.to_rgb16()),
+    };
+    encode(&image, opts, opts.dither)
+}
+
+/// Encodes the image, whose channels and depth are final, in the format given by `opts`, dithering it to a
+/// palette if `dither_palette` is set and the format is paletted
+fn encode(
+    image: &DynamicImage,
+    opts: &SqframeOptions,
+    dither_palette: bool,
+) -> Result<Vec<u8>, SqframeError> {
+    let image = match opts.format {
+        ImageFormat::Gif if dither_palette => {
+            let dithered = dither(image);
+            progress!("Dithering: done");
+            Cow::Owned(dithered)
+        }
+        _ => {
+            if dither_palette {
+                progress!(
+                    "Dithering only applies to paletted (GIF) outputs and 16-bit images saved at 8 bits, \
+                     ignoring it"
+                );
+            }
+            Cow::Borrowed(image)
+        }
+    };
+    let mut encoded = Cursor::new(Vec::new());
+    match opts.format {
+        ImageFormat::Jpeg if opts.dpi.is_some() || opts.quality.is_some() => {
+            let mut encoder =
+                JpegEncoder::new_with_quality(&mut encoded, opts.quality.unwrap_or(75));
+            if let Some(dpi) = opts.dpi {
+                encoder.set_pixel_density(PixelDensity::dpi(dpi));
+            }
+            image.write_with_encoder(encoder)
+        }
+        _ => image.write_to(&mut encoded, opts.format),
+    }
+    .map_err(SqframeError::Encode)?;
+    let encoded = encoded.into_inner();
+    Ok(match (opts.format, opts.dpi) {
+        // image's PNG encoder cannot write a resolution, so its chunk is added afterwards
+        (ImageFormat::Png, Some(dpi)) => metadata::png_with_dpi(&encoded, dpi),
+        _ => encoded,
+    })
+}
+
+/// Decodes an image from memory, frames it, and encodes the result according to `opts`, without
+/// touching the filesystem or the clipboard
+pub fn square_bytes(input: &[u8], opts: &SqframeOptions) -> Result<Vec<u8>, SqframeError> {
+    square_bytes_with_progress(input, opts, &|_, _| {})
+}
+
+/// Like `square_bytes`, but calls `on_progress` with each stage and the percentage of the whole process done
+/// once it is complete, for embedders that show progress without parsing stdout
+pub fn square_bytes_with_progress(
+    input: &[u8],
+    opts: &SqframeOptions,
+    on_progress: &dyn Fn(Stage, f32),
+) -> Result<Vec<u8>, SqframeError> {
+    let image = image::load_from_memory(input).map_err(SqframeError::Decode)?;
+    on_progress(Stage::Decode, Stage::Decode.percent());
+    let encoded = encode_image(
+        &square_image_with_progress(&image, opts, on_progress)?,
+        opts,
+    )?;
+    on_progress(Stage::Encode, Stage::Encode.percent());
+    Ok(encoded)
+}
+
+impl SqframeOptions {
+    /// Returns the options for an image of a batch (zip input or '--watch') that keeps its own `format`
+    fn with_format(&self, format: ImageFormat) -> SqframeOptions {
+        SqframeOptions {
+            format,
+            bit_depth: self.bit_depth.filter(|_| format == ImageFormat::Png),
+            dpi: self
+                .dpi
+                .filter(|_| matches!(format, ImageFormat::Png | ImageFormat::Jpeg)),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the options for saving another rendition of the output (a thumbnail or comparison) as `format`;
+    /// the background and corners are already drawn into it, so only the output options that `format` supports
+    /// are kept
+    fn for_extra_output(&self, format: ImageFormat) -> SqframeOptions {
+        let keeps_alpha = matches!(format, ImageFormat::Png | ImageFormat::Tiff);
+        SqframeOptions {
+            bit_depth: None,
+            channels: self
+                .channels
+                .filter(|&channels| channels == Channels::Rgb || keeps_alpha),
+            background: Background::Blur,
+            outer_radius: None,
+            ..self.with_format(format)
+        }
+    }
+
+    /// Returns the options for framing an image enlarged `factor` times, with their lengths in pixels scaled to
+    /// match
+    fn scaled_by(&self, factor: u32) -> SqframeOptions {
+        let factor_f = factor as f32;
+        SqframeOptions {
+            blur: self.blur * factor_f,
+            adaptive_blur: self
+                .adaptive_blur
+                .map(|(min_radius, max_radius)| (min_radius * factor_f, max_radius * factor_f)),
+            glow: self.glow.map(|glow| Glow {
+                radius: glow.radius * factor_f,
+                ..glow
+            }),
+            border: self.border.map(|border| border * factor),
+            outer_radius: self.outer_radius.map(|radius| radius * factor),
+            max_dimension: None,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the options with the settings of an image's sidecar applied over them
+    fn with_sidecar(&self, sidecar: &Sidecar) -> SqframeOptions {
+        SqframeOptions {
+            blur: sidecar.blur.unwrap_or(self.blur),
+            ..self.clone()
+        }
+    }
+}
+
+/// Parses the text of the sidecar `name`
+fn parse_sidecar(text: &str, name: &str) -> Result<Sidecar, SqframeError> {
+    let sidecar = sidecar::parse(text)
+        .map_err(|e| SqframeError::InvalidArgs(format!("invalid sidecar {name:?}: {e}")))?;
+    progress!("Read settings from {name:?}");
+    Ok(sidecar)
+}
+
+/// Reads the sidecar next to the image at `path` (named after it with `.sqframe` appended), `None` if there is
+/// none
+fn read_sidecar(path: &Path) -> Result<Option<Sidecar>, SqframeError> {
+    let mut sidecar_path = path.as_os_str().to_owned();
+    sidecar_path.push(format!(".{}", sidecar::EXTENSION));
+    let sidecar_path = PathBuf::from(sidecar_path);
+    let name = sidecar_path.display().to_string();
+    match fs::read_to_string(&sidecar_path) {
+        Ok(text) => parse_sidecar(&text, &name).map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(SqframeError::io(format!("could not read sidecar {name:?}"))(e)),
+    }
+}
+
+/// Reads the sidecar of the archive entry `name` from the archive, `None` if there is none
+fn read_zip_sidecar<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Option<Sidecar>, SqframeError> {
+    let name = format!("{name}.{}", sidecar::EXTENSION);
+    let mut text = String::new();
+    match archive.by_name(&name) {
+        Ok(mut file) => file
+            .read_to_string(&mut text)
+            .map_err(SqframeError::io(format!("could not read sidecar {name:?}")))?,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => {
+            return Err(SqframeError::io(format!("could not read sidecar {name:?}"))(e.into()))
+        }
+    };
+    parse_sidecar(&text, &name).map(Some)
+}
+
+/// Returns whether the path has a `.zip` extension
+fn is_zip(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Progress through a batch of a known number of images, with an estimate of the time left
+struct Progress {
+    total: usize,
+    done: usize,
+    start: Instant,
+}
+
+impl Progress {
+    fn new(total: usize) -> Progress {
+        Progress {
+            total,
+            done: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns the status of the next image, e.g. `[3/10, 20%, about 1m 04s left]`, and counts it as started
+    fn next(&mut self) -> String {
+        let (done, total) = (self.done, max(self.total, 1));
+        self.done += 1;
+        let position = format!("{}/{}, {}%", done + 1, self.total, done * 100 / total);
+        if done == 0 {
+            return format!("[{position}]");
+        }
+        let left = (self.start.elapsed().as_secs_f64() / done as f64
+            * total.saturating_sub(done) as f64)
+            .ceil() as u64;
+        if left < 60 {
+            format!("[{position}, about {left}s left]")
+        } else {
+            format!("[{position}, about {}m {:02}s left]", left / 60, left % 60)
+        }
+    }
+}
+
+/// Frames every image in the zip archive at `input_path`, each keeping its own format, and writes the results
+/// to a new zip archive if `output_path` ends in `.zip`, or into the directory `output_path` otherwise, then
+/// writes the manifest of what happened to each image to `manifest_path` if given
+fn frame_zip(
+    input_path: &str,
+    output_path: &Path,
+    manifest_path: Option<&Path>,
+    opts: &SqframeOptions,
+    overwrite: Overwrite,
+) -> Result<(), SqframeError> {
+    let file = File::open(input_path).map_err(SqframeError::io(format!(
+        "could not open archive {input_path:?}"
+    )))?;
+    let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|e| {
+        SqframeError::io(format!("could not read archive {input_path:?}"))(e.into())
+    })?;
+    progress!("Opened archive {input_path:?}");
+    let mut progress = Progress::new(
+        archive
+            .file_names()
+            .filter(|name| !name.ends_with('/') && ImageFormat::from_path(name).is_ok())
+            .count(),
+    );
+    let mut writer =
+        is_zip(&output_path.to_string_lossy()).then(|| ZipWriter::new(Cursor::new(Vec::new())));
+    let (mut framed, mut total) = (0, 0);
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| {
+            SqframeError::io(format!("could not read archive {input_path:?}"))(e.into())
+        })?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        let Some(relative_path) = entry.enclosed_name().map(Path::to_path_buf) else {
+            progress!("Skipping {name:?}, its path leaves the archive");
+            continue;
+        };
+        if relative_path
+            .extension()
+            .is_some_and(|extension| extension == sidecar::EXTENSION)
+        {
+            continue;
+        }
+        let Ok(format) = ImageFormat::from_path(&relative_path) else {
+            progress!("Skipping {name:?}, not an image");
+            continue;
+        };
+        total += 1;
+        let status = progress.next();
+        let entry_path = output_path.join(&relative_path);
+        let mut record = |status| {
+            entries.push(manifest::Entry {
+                input: name.clone(),
+                output: entry_path.display().to_string(),
+                dimensions: None,
+                status,
+            })
+        };
+        if writer.is_none() && overwrite.no_clobber && entry_path.exists() {
+            progress!(
+                "Skipping {name:?}, {:?} already exists",
+                entry_path.display()
+            );
+            record(Status::Skipped(String::from("output already exists")));
+            continue;
+        }
+        progress!("{status} Framing {name:?}...");
+        let mut bytes = Vec::new();
+        if let Err(e) = entry.read_to_end(&mut bytes) {
+            progress!("Could not read {name:?}: {e:?}");
+            record(Status::Failed(e.to_string()));
+            continue;
+        }
+        drop(entry);
+        let entry_opts = match read_zip_sidecar(&mut archive, &name) {
+            Ok(Some(sidecar)) => opts.with_format(format).with_sidecar(&sidecar),
+            Ok(None) => opts.with_format(format),
+            Err(e) => {
+                progress!("Could not frame {name:?}: {e}");
+                record(Status::Failed(e.to_string()));
+                continue;
+            }
+        };
+        let encoded = match square_bytes(&bytes, &entry_opts) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                progress!("Could not frame {name:?}: {e}");
+                record(Status::Failed(e.to_string()));
+                continue;
+            }
+        };
+        let dimensions = ImageReader::new(Cursor::new(&encoded))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok());
+        match &mut writer {
+            Some(writer) => {
+                writer
+                    .start_file(name.as_str(), FileOptions::default())
+                    .map_err(io::Error::from)
+                    .and_then(|_| writer.write_all(&encoded))
+                    .map_err(SqframeError::io(format!(
+                        "could not write {name:?} to the archive"
+                    )))?;
+            }
+            None => {
+                if write_output(&encoded, &entry_path, backup_dir(), overwrite)?
+                    == Written::Declined
+                {
+                    record(Status::Skipped(String::from(
+                        "replacing the output was declined",
+                    )));
+                    continue;
+                }
+            }
+        }
+        entries.push(manifest::Entry {
+            input: name,
+            output: entry_path.display().to_string(),
+            dimensions,
+            status: Status::Framed,
+        });
+        framed += 1;
+    }
+    if let Some(mut writer) = writer {
+        let archive_bytes = writer
+            .finish()
+            .map_err(|e| SqframeError::io("could not finish the output archive")(e.into()))?
+            .into_inner();
+        if write_output(&archive_bytes, output_path, backup_dir(), overwrite)? == Written::Saved {
+            progress!("Saved archive to {:?}!", output_path.display());
+        }
+    }
+    progress!("Framed {framed} of {total} images");
+    if let Some(manifest_path) = manifest_path {
+        fs::write(manifest_path, manifest::render(manifest_path, &entries)).map_err(
+            SqframeError::io(format!(
+                "could not write manifest {:?}",
+                manifest_path.display()
+            )),
+        )?;
+        progress!("Saved manifest to {:?}!", manifest_path.display());
+    }
+    Ok(())
+}
+
+/// Returns the size of every image file (recognized by its extension) directly inside `dir`
+fn list_images(dir: &Path) -> Result<HashMap<PathBuf, u64>, SqframeError> {
+    let read_error = || SqframeError::io(format!("could not read directory {:?}", dir.display()));
+    let mut images = HashMap::new();
+    for entry in fs::read_dir(dir).map_err(read_error())? {
+        let entry = entry.map_err(read_error())?;
+        let path = entry.path();
+        if ImageFormat::from_path(&path).is_err() {
+            continue;
+        }
+        if let Some(metadata) = fs::metadata(&path)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+        {
+            images.insert(path, metadata.len());
+        }
+    }
+    Ok(images)
+}
+
+/// Frames an image added to a watched directory into the directory `output_path`, reporting (rather than
+/// returning) failures so that watching goes on
+fn frame_watched(path: &Path, output_path: &Path, opts: &SqframeOptions, overwrite: Overwrite) {
+    let (Some(name), Ok(format)) = (path.file_name(), ImageFormat::from_path(path)) else {
+        return;
+    };
+    progress!("Framing {:?}...", path.display());
+    let framed = fs::read(path)
+        .map_err(SqframeError::io(format!(
+            "could not read {:?}",
+            path.display()
+        )))
+        .and_then(|bytes| {
+            let opts = match read_sidecar(path)? {
+                Some(sidecar) => opts.with_format(format).with_sidecar(&sidecar),
+                None => opts.with_format(format),
+            };
+            square_bytes(&bytes, &opts)
+        })
+        .and_then(|encoded| {
+            write_output(&encoded, &output_path.join(name), backup_dir(), overwrite)
+        });
+    match framed {
+        Ok(Written::Saved) => progress!("Saved image to {:?}!", output_path.join(name).display()),
+        Ok(Written::Unchanged | Written::Declined) => {}
+        Err(e) => progress!("Could not frame {:?}: {e}", path.display()),
+    }
+}
+
+/// Returns the directory holding `path`, the current directory for a bare file name
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Opens `path` with the desktop's default application for it, warning instead of failing since the output has
+/// already been saved by then
+fn open_in_viewer(path: &Path) {
+    #[cfg(target_os = "macos")]
+    let mut command = process::Command::new("open");
+    #[cfg(windows)]
+    let mut command = {
+        // `start` takes its first quoted argument as the window title
+        let mut command = process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = process::Command::new("xdg-open");
+    match command.arg(path).spawn() {
+        Ok(_) => progress!("Opened {:?}", path.display()),
+        Err(e) => eprintln!("WARNING: could not open {:?}: {e}", path.display()),
+    }
+}
+
+/// Frames every image that is added to `dir` into the directory `output_path` until the process is
+/// interrupted, polling every `WATCH_INTERVAL`; images already in `dir` are left alone, and new ones are only
+/// framed once their size stops changing, so that files still being written are not read
+fn watch_dir(
+    dir: &Path,
+    output_path: &Path,
+    opts: &SqframeOptions,
+    overwrite: Overwrite,
+) -> Result<(), SqframeError> {
+    if fs::canonicalize(dir)
+        .is_ok_and(|dir| fs::canonicalize(output_path).is_ok_and(|out| out == dir))
+    {
+        return Err(SqframeError::InvalidArgs(String::from(
+            "'--watch' needs an output directory other than the watched one",
+        )));
+    }
+    let mut seen: HashSet<PathBuf> = list_images(dir)?.into_keys().collect();
+    let mut pending: HashMap<PathBuf, u64> = HashMap::new();
+    progress!(
+        "Watching {:?} for new images, press Ctrl-C to stop",
+        dir.display()
+    );
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        for (path, size) in list_images(dir)? {
+            if seen.contains(&path) || pending.insert(path.clone(), size) != Some(size) {
+                continue;
+            }
+            pending.remove(&path);
+            frame_watched(&path, output_path, opts, overwrite);
+            seen.insert(path);
+        }
+    }
+}
+
+pub fn run(args: Args) -> Result<(), SqframeError> {
+    // before anything that may fail, since errors are colored when printed
+    if args.no_color {
+        colored::control::set_override(false);
+    }
+    match &args.command {
+        Some(Command::Info { path }) => return print_info(path),
+        Some(Command::Restore { path }) => return restore(Path::new(path)),
+        None => {}
+    }
+    if let Some(threads) = args.threads {
+        ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()
+            .map_err(|e| SqframeError::InvalidArgs(format!("could not limit threads: {e}")))?;
+    }
+    // the data URI and the bare backup paths are meant to be read by scripts
+    PROGRESS_TO_STDERR.store(args.base64 || args.print_backup_path, Ordering::Relaxed);
+    let opts = args.options()?;
+    #[cfg(feature = "server")]
+    if let Some(port) = args.serve {
+        return server::serve(port, opts);
+    }
+    let overwrite = Overwrite {
+        skip_unchanged: args.skip_unchanged,
+        no_clobber: args.no_clobber,
+        force: args.force,
+        print_backup_path: args.print_backup_path,
+        verify: args.verify,
+        lang: args.lang.unwrap_or_else(Lang::from_env),
+    };
+    if let Some(input_path) = args.input_path.as_deref().filter(|path| is_zip(path)) {
+        let Some(output_path) = &args.output_path else {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "a zip input needs an output path (a .zip file or a directory)",
+            )));
+        };
+        frame_zip(
+            input_path,
+            Path::new(output_path),
+            args.manifest.as_deref(),
+            &opts,
+            overwrite,
+        )?;
+        if args.open {
+            let output_path = Path::new(output_path);
+            if output_path.is_dir() {
+                open_in_viewer(output_path);
+            } else {
+                open_in_viewer(parent_dir(output_path));
+            }
+        }
+        return Ok(());
+    }
+    if args.manifest.is_some() {
+        return Err(SqframeError::InvalidArgs(String::from(
+            "'--manifest' is only supported for a zip input",
+        )));
+    }
+    if let (Some(dir), Some(output_path)) = (&args.watch, &args.output_path) {
+        return watch_dir(dir, Path::new(output_path), &opts, overwrite);
+    }
+    let to_clipboard = args.output_path.is_none() && !args.base64;
+    if to_clipboard {
+        if args.bit_depth.is_some() {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "'--bit-depth' is only supported for PNG output",
+            )));
+        }
+        if args.dpi.is_some() {
+            return Err(SqframeError::InvalidArgs(String::from(
+                "'--dpi' is only supported for PNG and JPEG output",
+            )));
+        }
+    } else {
+        opts.validate()?;
+    }
+    let keep_metadata = !args.strip_metadata
+        && (args.keep_metadata || args.preset.is_some_and(Preset::keeps_metadata));
+    let mut metadata = read_metadata(&args.input_path, keep_metadata || args.convert_srgb)?;
+    // once converted, the pixels no longer match the input's profile
+    let icc = if args.convert_srgb {
+        metadata.icc.take()
+    } else {
+        None
+    };
+    if !keep_metadata {
+        metadata = Metadata::default();
+    }
+    if let Some(orientation) = args.set_orientation {
+        metadata.exif = Some(metadata::set_orientation(
+            metadata.exif.as_deref(),
+            orientation,
+        ));
+    }
+    let retries = Retries {
+        attempts: args.clipboard_attempts,
+        delay: Duration::from_millis(args.clipboard_retry_delay),
+    };
+    #[cfg(feature = "capture")]
+    let captured = if args.screenshot {
+        Some(capture::screen()?)
+    } else {
+        None
+    };
+    #[cfg(not(feature = "capture"))]
+    let captured = None;
+    #[cfg(feature = "video")]
+    let captured = match (captured, args.video_time, &args.input_path) {
+        (None, Some(seconds), Some(in_path)) => Some(video::frame_at(in_path, seconds)?),
+        (captured, ..) => captured,
+    };
+    let captured = match (captured, &args.input_base64) {
+        (None, Some(data)) => Some(open_image_from_base64(data)?),
+        (captured, _) => captured,
+    };
+    let mut image = match captured {
+        Some(image) => image,
+        None => open_image(
+            args.input_path,
+            args.input_format,
+            args.frame,
+            args.timeout,
+            retries,
+        )?,
+    };
+    if args.convert_srgb {
+        match icc {
+            Some(icc) => {
+                image = color::to_srgb(&image, &icc).map_err(|e| {
+                    SqframeError::InvalidArgs(format!(
+                        "could not convert image to sRGB ('--convert-srgb'): {e}"
+                    ))
+                })?;
+                progress!("sRGB conversion: done");
+            }
+            None => progress!("Input has no ICC profile, treating it as sRGB"),
+        }
+    }
+    if args.stats {
+        print_stats(&image);
+    }
+    let final_image = square_image(&image, &opts)?;
+    let thumbnail = args.thumbnail.map(|thumbnail| {
+        let size = min(thumbnail.size, final_image.width());
+        (
+            final_image.resize(size, size, FilterType::Lanczos3),
+            thumbnail,
+        )
+    });
+    let comparison = args
+        .compare
+        .map(|(path, format)| (side_by_side(&image, &final_image), path, format));
+    // with '--sizes' there are several outputs, so their directory is opened
+    let opened_path = match &args.output_path {
+        Some(output_path) if args.open && args.sizes.is_empty() => Some(PathBuf::from(output_path)),
+        Some(output_path) if args.open => Some(parent_dir(Path::new(output_path)).to_path_buf()),
+        _ => None,
+    };
+    if let (false, Some(output_path)) = (args.sizes.is_empty(), &args.output_path) {
+        for size in args.sizes {
+            let written = save_image_to_path(
+                final_image.resize_exact(size, size, FilterType::Lanczos3),
+                &sized_path(output_path, size),
+                backup_dir(),
+                &metadata,
+                &opts,
+                overwrite,
+            )?;
+            if written == Written::Declined {
+                return Ok(());
+            }
+        }
+    } else if args.base64 {
+        print_data_uri(&final_image, &metadata, &opts)?;
+    } else {
+        let clipboard = ClipboardOutput {
+            format: args.clipboard_format,
+            quality: args.clipboard_quality,
+            bgra: args.compat_clipboard,
+            retries,
+        };
+        let clipboard_copy = args.also_clipboard.then(|| final_image.clone());
+        let written = save_image(
+            final_image,
+            args.output_path,
+            &metadata,
+            &opts,
+            clipboard,
+            overwrite,
+        )?;
+        // nothing else is saved or opened once the user has declined replacing the output
+        if written == Written::Declined {
+            return Ok(());
+        }
+        if let Some(image) = clipboard_copy {
+            if save_image_to_clipboard(image, clipboard, overwrite)? == Written::Declined {
+                return Ok(());
+            }
+        }
+    }
+    if let Some(path) = opened_path {
+        open_in_viewer(&path);
+    }
+    if let Some((comparison_image, path, format)) = comparison {
+        progress!("Saving comparison...");
+        let comparison_opts = opts.for_extra_output(format);
+        save_image_to_path(
+            comparison_image,
+            &path,
+            backup_dir(),
+            &metadata,
+            &comparison_opts,
+            overwrite,
+        )?;
+    }
+    if let Some((thumbnail_image, thumbnail)) = thumbnail {
+        progress!("Saving thumbnail...");
+        let thumbnail_opts = opts.for_extra_output(thumbnail.format);
+        save_image_to_path(
+            thumbnail_image,
+            &thumbnail.path,
+            backup_dir(),
+            &metadata,
+            &thumbnail_opts,
+            overwrite,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use image::{GrayImage, Luma};
+
+    #[test]
+    fn edge_density_of_flat_image_is_zero() {
+        let flat = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, Rgb([120, 80, 40])));
+        assert_eq!(edge_density(&flat), 0.);
+    }
+
+    #[test]
+    fn edge_density_grows_with_detail() {
+        let gradient =
+            DynamicImage::ImageLuma8(GrayImage::from_fn(64, 64, |x, _| Luma([x as u8 * 4])));
+        let checkerboard = DynamicImage::ImageLuma8(GrayImage::from_fn(64, 64, |x, y| {
+            Luma([((x / 2 + y / 2) % 2 * 255) as u8])
+        }));
+        let (smooth, busy) = (edge_density(&gradient), edge_density(&checkerboard));
+        assert!(
+            smooth > 0. && smooth < BUSY_EDGE_DENSITY,
+            "gradient density {smooth}"
+        );
+        assert!(
+            (BUSY_EDGE_DENSITY..=1.).contains(&busy),
+            "checkerboard density {busy}"
+        );
+    }
+
+    #[test]
+    fn edge_density_of_tiny_image_is_zero() {
+        let tiny = DynamicImage::ImageLuma8(GrayImage::from_fn(2, 2, |x, _| Luma([x as u8 * 255])));
+        assert_eq!(edge_density(&tiny), 0.);
+    }
+
+    /// Blurs `values` as one line of single-channel pixels, the slow way
+    fn naive_box_blur(values: &[f32], radius: isize) -> Vec<f32> {
+        let len = values.len() as isize;
+        (0..len)
+            .map(|i| {
+                let total: f32 = (i - radius..=i + radius)
+                    .map(|j| values[j.clamp(0, len - 1) as usize])
+                    .sum();
+                total / (2 * radius + 1) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn box_blur_line_matches_naive_blur() {
+        let values = [0., 1., 0.25, 0.75, 1.];
+        for radius in [1, 2, 4, 5, 12] {
+            let mut pixels: Vec<[f32; 3]> = values.iter().map(|&value| [value; 3]).collect();
+            box_blur_line(&mut pixels, 0, 1, values.len(), radius, &mut Vec::new());
+            for (blurred, expected) in pixels.iter().zip(naive_box_blur(&values, radius as isize)) {
+                assert!((blurred[0] - expected).abs() < 1e-5, "radius {radius}");
+            }
+        }
+    }
+
+    #[test]
+    fn box_blur_line_with_huge_radius_averages_towards_the_ends() {
+        let mut pixels = vec![[0.; 3], [1.; 3], [1.; 3]];
+        box_blur_line(&mut pixels, 0, 1, 3, 1_000_000_000, &mut Vec::new());
+        // a radius dwarfing the line weighs its two end pixels equally
+        for pixel in pixels {
+            assert!((pixel[0] - 0.5).abs() < 1e-3, "{pixel:?}");
+        }
+    }
+
+    #[test]
+    fn linear_blur_keeps_high_contrast_bright() {
+        let stripes = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 32, |x, _| {
+            Rgb([if x % 2 == 0 { 255 } else { 0 }; 3])
+        }));
+        let mean = |image: DynamicImage| {
+            let rgb = image.to_rgb8();
+            rgb.pixels().map(|pixel| pixel.0[0] as f32).sum::<f32>() / rgb.pixels().len() as f32
+        };
+        let (srgb, linear) = (
+            mean(blur(&stripes, 8., 1).unwrap()),
+            mean(blur_linear(&stripes, 8., 1).unwrap()),
+        );
+        // averaging black and white gives half the light, which sRGB encodes as 188 rather than 128
+        assert!((srgb - 127.5).abs() < 3., "sRGB blur mean {srgb}");
+        assert!((linear - 188.).abs() < 3., "linear blur mean {linear}");
+    }
+
+    /// Encodes an indexed-color PNG of 8 or 4 bits per pixel whose pixel at (x, y) is `index(x, y)`, with
+    /// `alpha` as its tRNS chunk
+    fn indexed_png(
+        (width, height): (u32, u32),
+        bit_depth: u8,
+        palette: &[[u8; 3]],
+        alpha: &[u8],
+        index: impl Fn(u32, u32) -> u8,
+    ) -> Vec<u8> {
+        let chunk = |png: &mut Vec<u8>, kind: &[u8], data: &[u8]| {
+            png.extend((data.len() as u32).to_be_bytes());
+            png.extend(kind);
+            png.extend(data);
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(kind);
+            crc.update(data);
+            png.extend(crc.finalize().to_be_bytes());
+        };
+        let mut rows = Vec::new();
+        for y in 0..height {
+            // filter type None
+            rows.push(0);
+            let indices: Vec<u8> = (0..width).map(|x| index(x, y)).collect();
+            match bit_depth {
+                8 => rows.extend(indices),
+                4 => rows.extend(
+                    indices
+                        .chunks(2)
+                        .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)),
+                ),
+                _ => unreachable!(),
+            }
+        }
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(&rows).unwrap();
+        let mut header = Vec::new();
+        header.extend(width.to_be_bytes());
+        header.extend(height.to_be_bytes());
+        // bit depth, color type 3 (indexed), compression, filter and interlace methods
+        header.extend([bit_depth, 3, 0, 0, 0]);
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut png, b"IHDR", &header);
+        chunk(&mut png, b"PLTE", palette.concat().as_slice());
+        chunk(&mut png, b"tRNS", alpha);
+        chunk(&mut png, b"IDAT", &zlib.finish().unwrap());
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    /// Frames an indexed PNG and checks that its opaque pixels come out unchanged, centered in the square
+    fn check_indexed_png_is_framed((width, height): (u32, u32), bit_depth: u8) {
+        let palette = [[200, 30, 30], [30, 200, 30], [30, 30, 200], [240, 240, 240]];
+        // the last entry is fully transparent
+        let alpha = [255, 255, 255, 0];
+        let index = |x: u32, y: u32| ((x / 7 + y / 5) % 4) as u8;
+        let png = indexed_png((width, height), bit_depth, &palette, &alpha, index);
+        let source = image::load_from_memory(&png).unwrap();
+        assert_eq!(source.dimensions(), (width, height));
+        // the tRNS chunk makes the decoded image carry alpha
+        assert!(source.color().has_alpha());
+        let framed = square_bytes(&png, &SqframeOptions::default()).unwrap();
+        let framed = image::load_from_memory(&framed).unwrap().to_rgba8();
+        let side = max(width, height);
+        assert_eq!(framed.dimensions(), (side, side));
+        let (left, top) = ((side - width) / 2, (side - height) / 2);
+        for (x, y) in (0..height).flat_map(|y| (0..width).map(move |x| (x, y))) {
+            let index = index(x, y) as usize;
+            if alpha[index] == 255 {
+                let [r, g, b] = palette[index];
+                assert_eq!(framed.get_pixel(left + x, top + y).0, [r, g, b, 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn frames_8_bit_indexed_png() {
+        check_indexed_png_is_framed((90, 40), 8);
+    }
+
+    #[test]
+    fn frames_4_bit_indexed_png() {
+        check_indexed_png_is_framed((41, 90), 4);
+    }
+
+    #[test]
+    fn placement_offsets_follow_alignment() {
+        // 7 pixels left over horizontally and 5 vertically, odd remainders that centering rounds down
+        let (bg, fg) = ((11, 9), (4, 4));
+        let expected = [
+            (Align::Center, (3, 2)),
+            (Align::Top, (3, 0)),
+            (Align::Bottom, (3, 5)),
+            (Align::Left, (0, 2)),
+            (Align::Right, (7, 2)),
+            (Align::TopLeft, (0, 0)),
+            (Align::TopRight, (7, 0)),
+            (Align::BottomLeft, (0, 5)),
+            (Align::BottomRight, (7, 5)),
+        ];
+        for (align, offsets) in expected {
+            assert_eq!(placement_offsets(bg, fg, align), offsets, "{align:?}");
+        }
+    }
+
+    #[test]
+    fn placement_offsets_of_larger_image_are_zero() {
+        assert_eq!(
+            placement_offsets((10, 10), (12, 4), Align::BottomRight),
+            (0, 6)
+        );
+        assert_eq!(placement_offsets((10, 10), (12, 14), Align::Center), (0, 0));
+    }
+
+    #[test]
+    fn biased_offsets_move_from_center() {
+        assert_eq!(biased_offsets((11, 9), (4, 4), (0., 0.)), (3, 2));
+        assert_eq!(biased_offsets((11, 9), (4, 4), (0.5, -0.5)), (7, 0));
+        assert_eq!(biased_offsets((100, 100), (50, 100), (0.25, 0.25)), (38, 0));
+    }
+
+    /// A clipboard that records what is copied to it and how often it was asked before being replaced
+    #[derive(Default)]
+    struct FakeClipboard {
+        /// Answer to the replace prompt
+        refuse: bool,
+        prompts: usize,
+        copies: Vec<(Vec<u8>, u32, u32)>,
+    }
+
+    impl ClipboardTarget for FakeClipboard {
+        fn confirm_replace(&mut self, _lang: Lang) -> ConfirmResult {
+            self.prompts += 1;
+            if self.refuse {
+                ConfirmResult::Stop
+            } else {
+                ConfirmResult::Continue
+            }
+        }
+
+        fn copy(
+            &mut self,
+            payload: &ClipboardPayload,
+            width: u32,
+            height: u32,
+        ) -> Result<(), String> {
+            let ClipboardPayload::Bitmap(bytes) = payload else {
+                return Err(String::from("expected a bitmap"));
+            };
+            self.copies.push((bytes.clone(), width, height));
+            Ok(())
+        }
+    }
+
+    fn clipboard_output() -> ClipboardOutput {
+        ClipboardOutput {
+            format: ClipboardFormat::Bitmap,
+            quality: 85,
+            bgra: false,
+            retries: Retries {
+                attempts: 1,
+                delay: Duration::ZERO,
+            },
+        }
+    }
+
+    fn overwrite(force: bool) -> Overwrite {
+        Overwrite {
+            skip_unchanged: false,
+            no_clobber: false,
+            force,
+            print_backup_path: false,
+            verify: false,
+            lang: Lang::En,
+        }
+    }
+
+    #[test]
+    fn forced_clipboard_copy_never_prompts() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(3, 2, Rgb([1, 2, 3])));
+        let mut target = FakeClipboard {
+            // a prompt would stop the copy, and is counted
+            refuse: true,
+            ..FakeClipboard::default()
+        };
+        copy_to_clipboard(image, clipboard_output(), overwrite(true), &mut target).unwrap();
+        assert_eq!(target.prompts, 0);
+        assert_eq!(target.copies.len(), 1);
+        let (bytes, width, height) = &target.copies[0];
+        assert_eq!((*width, *height), (3, 2));
+        assert_eq!(bytes[..4], [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn clipboard_copy_prompts_without_force() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(3, 2, Rgb([1, 2, 3])));
+        let mut target = FakeClipboard::default();
+        copy_to_clipboard(image, clipboard_output(), overwrite(false), &mut target).unwrap();
+        assert_eq!(target.prompts, 1);
+        assert_eq!(target.copies.len(), 1);
+    }
+
+    #[test]
+    fn declined_clipboard_copy_returns_to_the_caller() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(3, 2, Rgb([1, 2, 3])));
+        let mut target = FakeClipboard {
+            refuse: true,
+            ..FakeClipboard::default()
+        };
+        let written = copy_to_clipboard(image, clipboard_output(), overwrite(false), &mut target);
+        assert_eq!(written.unwrap(), Written::Declined);
+        assert_eq!(target.prompts, 1);
+        assert!(target.copies.is_empty());
+    }
+
+    #[test]
+    fn clipboard_bitmap_orders_channels() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([10, 20, 30, 40])));
+        assert_eq!(clipboard_bitmap(&image, false), [10, 20, 30, 40]);
+        assert_eq!(clipboard_bitmap(&image, true), [30, 20, 10, 40]);
+    }
+
+    #[test]
+    fn clipboard_bitmap_is_rgba8_for_every_pixel_type() {
+        let images = [
+            DynamicImage::ImageLuma8(GrayImage::from_pixel(2, 1, Luma([90]))),
+            DynamicImage::ImageLumaA8(image::GrayAlphaImage::from_pixel(
+                2,
+                1,
+                image::LumaA([90, 128]),
+            )),
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 1, Rgb([90, 90, 90]))),
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 1, Rgba([90, 90, 90, 128]))),
+            DynamicImage::ImageRgb16(image::ImageBuffer::from_pixel(
+                2,
+                1,
+                Rgb([90 * 257, 90 * 257, 90 * 257]),
+            )),
+            DynamicImage::ImageRgba32F(image::Rgba32FImage::from_pixel(
+                2,
+                1,
+                Rgba([90. / 255., 90. / 255., 90. / 255., 128. / 255.]),
+            )),
+        ];
+        for image in images {
+            let alpha = if image.color().has_alpha() { 128 } else { 255 };
+            assert_eq!(
+                clipboard_bitmap(&image, false),
+                [90, 90, 90, alpha, 90, 90, 90, alpha],
+                "{:?}",
+                image.color()
+            );
+        }
+    }
+
+    #[test]
+    fn compat_clipboard_copies_bgra() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([10, 20, 30, 40])));
+        let mut target = FakeClipboard::default();
+        let clipboard = ClipboardOutput {
+            bgra: true,
+            ..clipboard_output()
+        };
+        copy_to_clipboard(image, clipboard, overwrite(true), &mut target).unwrap();
+        assert_eq!(target.copies[0].0, [30, 20, 10, 40]);
+    }
+
+    #[test]
+    fn overlay_copies_rgb8_foreground_byte_exactly() {
+        let bg = DynamicImage::ImageRgb8(RgbImage::from_pixel(9, 7, Rgb([7, 8, 9])));
+        let fg = RgbImage::from_fn(5, 3, |x, y| {
+            Rgb([x as u8 * 50 + 1, y as u8 * 80 + 2, (x * y) as u8 * 13 + 3])
+        });
+        let overlaid = overlay(
+            &bg,
+            &DynamicImage::ImageRgb8(fg.clone()),
+            (3, 2),
+            false,
+            Fit::Error,
+        )
+        .unwrap();
+        let overlaid = overlaid.as_rgb8().expect("RGB8 result");
+        for (x, y) in (0..7).flat_map(|y| (0..9).map(move |x| (x, y))) {
+            let expected = if (3..8).contains(&x) && (2..5).contains(&y) {
+                *fg.get_pixel(x - 3, y - 2)
+            } else {
+                Rgb([7, 8, 9])
+            };
+            assert_eq!(*overlaid.get_pixel(x, y), expected, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn overlay_rejects_foreground_that_does_not_fit() {
+        let bg = DynamicImage::ImageRgb8(RgbImage::new(10, 10));
+        let fg = DynamicImage::ImageRgb8(RgbImage::from_pixel(12, 4, Rgb([255; 3])));
+        let error = overlay(&bg, &fg, (0, 3), false, Fit::Error).unwrap_err();
+        assert!(matches!(error, SqframeError::Dimension(_)), "{error}");
+        // fitting in size is not enough if the position pushes the image past an edge
+        let fg = DynamicImage::ImageRgb8(RgbImage::from_pixel(6, 6, Rgb([255; 3])));
+        assert!(overlay(&bg, &fg, (5, 0), false, Fit::Error).is_err());
+        assert!(overlay(&bg, &fg, (4, 4), false, Fit::Error).is_ok());
+    }
+
+    #[test]
+    fn overlay_contains_foreground_that_does_not_fit() {
+        let bg = DynamicImage::ImageRgb8(RgbImage::new(10, 10));
+        let fg = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 8, Rgb([255; 3])));
+        let overlaid = overlay(&bg, &fg, (0, 1), false, Fit::Contain)
+            .unwrap()
+            .into_rgb8();
+        assert_eq!(overlaid.dimensions(), (10, 10));
+        // shrunk to 10x4 and centered vertically, leaving 3 rows of background above and below
+        let is_foreground = |y: u32| (0..10).all(|x| overlaid.get_pixel(x, y).0 == [255; 3]);
+        let is_background = |y: u32| (0..10).all(|x| overlaid.get_pixel(x, y).0 == [0; 3]);
+        assert!((0..3).chain(7..10).all(is_background));
+        assert!((3..7).all(is_foreground));
+        // an image small enough is moved back inside instead
+        let fg = DynamicImage::ImageRgb8(RgbImage::from_pixel(6, 6, Rgb([255; 3])));
+        let overlaid = overlay(&bg, &fg, (7, 2), false, Fit::Contain)
+            .unwrap()
+            .into_rgb8();
+        assert_eq!(overlaid.get_pixel(4, 2).0, [255; 3]);
+        assert_eq!(overlaid.get_pixel(3, 2).0, [0; 3]);
+        assert_eq!(overlaid.get_pixel(9, 7).0, [255; 3]);
+        assert_eq!(overlaid.get_pixel(9, 8).0, [0; 3]);
+    }
+
+    /// Returns a new, empty directory for a test to write into
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sqframe-test-{name}-{}", process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn failed_save_restores_the_backup() {
+        let dir = test_dir("failed-save");
+        let (output_path, backups) = (dir.join("out.png"), dir.join("backups"));
+        fs::create_dir(&backups).unwrap();
+        fs::write(&output_path, b"original").unwrap();
+        let saved = write_output_with(
+            b"framed",
+            &output_path,
+            backups.clone(),
+            overwrite(true),
+            |path, bytes| {
+                // the original has been moved to the backups by now
+                assert!(!path.exists());
+                assert_eq!(fs::read_dir(&backups).unwrap().count(), 1);
+                fs::write(path, &bytes[..2])?;
+                Err(io::Error::other("disk full"))
+            },
+        );
+        assert!(matches!(saved, Err(SqframeError::Io { .. })));
+        assert_eq!(fs::read(&output_path).unwrap(), b"original");
+        assert_eq!(fs::read_dir(&backups).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn flipping_twice_returns_the_original() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(5, 3, |x, y| {
+            Rgb([x as u8 * 40, y as u8 * 90, (x + y) as u8])
+        }));
+        for flip in [Flip::Horizontal, Flip::Vertical, Flip::Both] {
+            let flipped = flip.apply(&image);
+            assert_eq!(flipped.dimensions(), (5, 3));
+            assert_ne!(flipped, image, "{flip:?}");
+            assert_eq!(flip.apply(&flipped), image, "{flip:?}");
+        }
+        assert_eq!(Flip::Both.apply(&image), image.rotate180());
+    }
+
+    #[test]
+    fn extreme_offsets_keep_the_image_inside() {
+        let (bg, fg) = ((100, 60), (40, 60));
+        for bias in [1., 1e6, f32::MAX] {
+            assert_eq!(biased_offsets(bg, fg, (bias, bias)), (60, 0), "{bias}");
+            assert_eq!(biased_offsets(bg, fg, (-bias, -bias)), (0, 0), "{bias}");
+        }
+        // a full slack of u32::MAX must not overflow either
+        assert_eq!(
+            biased_offsets((u32::MAX, 1), (0, 1), (10., 0.)),
+            (u32::MAX, 0)
+        );
+    }
+
+    #[test]
+    fn extreme_offset_frames_the_image_at_the_edge() {
+        let input = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 20, Rgb([255, 0, 0])));
+        let mut png = Vec::new();
+        input
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let opts = SqframeOptions {
+            offset: (0., 1e9),
+            ..SqframeOptions::default()
+        };
+        let framed = image::load_from_memory(&square_bytes(&png, &opts).unwrap())
+            .unwrap()
+            .into_rgb8();
+        assert_eq!(framed.dimensions(), (40, 40));
+        // pushed to the bottom edge: the last 20 rows are the image
+        assert!((20..40).all(|y| (0..40).all(|x| framed.get_pixel(x, y).0 == [255, 0, 0])));
+    }
+
+    #[test]
+    fn mismatched_blur_buffer_is_an_error() {
+        for len in [0, 11, 13] {
+            let mut colors = vec![[9; 3]; len];
+            let error = blur_colors(&mut colors, (4, 3), 2., 1).unwrap_err();
+            assert!(matches!(error, SqframeError::Dimension(_)), "{error}");
+            // the buffer is left alone rather than partly blurred
+            assert_eq!(colors, vec![[9; 3]; len]);
+        }
+        let mut colors = vec![[9; 3]; 12];
+        assert!(blur_colors(&mut colors, (4, 3), 2., 1).is_ok());
+        let mut colors = Vec::new();
+        assert!(blur_colors(&mut colors, (0, 5), 2., 1).is_ok());
+    }
+
+    #[test]
+    fn single_blur_pass_matches_one_gaussian_blur() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(23, 17, |x, y| {
+            Rgb([(x * 11) as u8, (y * 15) as u8, ((x ^ y) * 8) as u8])
+        }));
+        let mut expected = get_colors(&image);
+        gaussian_blur(&mut expected, 23, 17, 5.);
+        let blurred = blur(&image, 5., 1).unwrap().into_rgb8().into_raw();
+        assert_eq!(blurred, expected.into_flattened());
+        // four passes each blur with radius 5 / √4
+        let split = blur(&image, 5., 4).unwrap().into_rgb8().into_raw();
+        let mut quarter = get_colors(&image);
+        for _ in 0..4 {
+            gaussian_blur(&mut quarter, 23, 17, 2.5);
+        }
+        assert_eq!(split, quarter.into_flattened());
+    }
+
+    #[test]
+    fn saves_into_missing_nested_directories() {
+        let dir = test_dir("nested-output");
+        let output_path = dir.join("nested/dir/out.png");
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([1, 2, 3])));
+        save_image_to_path(
+            image.clone(),
+            &output_path,
+            dir.join("backups"),
+            &Metadata::default(),
+            &SqframeOptions::default(),
+            overwrite(false),
+        )
+        .unwrap();
+        assert_eq!(image::open(&output_path).unwrap(), image);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn opens_mapped_and_forced_format_inputs() {
+        let dir = test_dir("mapped-input");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("input.png");
+        let image =
+            DynamicImage::ImageRgb8(RgbImage::from_fn(5, 3, |x, y| Rgb([x as u8, y as u8, 7])));
+        image.save(&path).unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(open_image_from_path(path, None, None).unwrap(), image);
+        assert_eq!(
+            open_image_from_path(path, Some(ImageFormat::Png), None).unwrap(),
+            image
+        );
+        assert!(open_image_from_path(path, Some(ImageFormat::Bmp), None).is_err());
+        fs::write(dir.join("empty.png"), []).unwrap();
+        assert!(open_image_from_path(dir.join("empty.png").to_str().unwrap(), None, None).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mirror_pad_reflects_without_repeating_the_edge() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(3, 1, |x, _| Rgb([x as u8; 3])));
+        let padded = mirror_pad(&image, 2).into_rgb8();
+        assert_eq!(padded.dimensions(), (7, 5));
+        let row: Vec<u8> = (0..7).map(|x| padded.get_pixel(x, 0).0[0]).collect();
+        assert_eq!(row, [2, 1, 0, 1, 2, 1, 0]);
+        // the single row is repeated above and below
+        assert_eq!(padded.get_pixel(3, 0), padded.get_pixel(3, 4));
+        let wide = mirror_pad(&image, 5).into_rgb8();
+        let row: Vec<u8> = (0..13).map(|x| wide.get_pixel(x, 0).0[0]).collect();
+        assert_eq!(row, [1, 0, 1, 2, 1, 0, 1, 2, 1, 0, 1, 2, 1]);
+    }
+
+    #[test]
+    fn symmetric_blur_does_not_overweight_edge_rows() {
+        // a bright line along the top edge of an otherwise dark image
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 20, |_, y| {
+            Rgb([if y == 0 { 250 } else { 50 }; 3])
+        }));
+        let row_means = |symmetric_blur: bool| {
+            let opts = SqframeOptions {
+                symmetric_blur,
+                ..SqframeOptions::default()
+            };
+            let bg = blurred_background(&image, &opts, &|_, _| {})
+                .unwrap()
+                .into_rgb8();
+            let side = bg.width();
+            let mean = |y: u32| {
+                (0..side)
+                    .map(|x| bg.get_pixel(x, y).0[0] as f32)
+                    .sum::<f32>()
+                    / side as f32
+            };
+            (mean(0), mean(side - 1))
+        };
+        let (clamped_top, clamped_bottom) = row_means(false);
+        let (mirrored_top, mirrored_bottom) = row_means(true);
+        // extending the edge weighs the bright line as if it were many rows thick
+        assert!(
+            clamped_top > mirrored_top + 20.,
+            "top row {clamped_top} without, {mirrored_top} with symmetric blur"
+        );
+        // the bottom edge, dark all the way, is the same either way
+        assert!((clamped_bottom - mirrored_bottom).abs() < 1.);
+        assert!(mirrored_top > mirrored_bottom);
+    }
+
+    #[test]
+    fn frames_to_gif_with_and_without_dithering() {
+        // far more than a GIF's 256 colors
+        let input = DynamicImage::ImageRgb8(RgbImage::from_fn(120, 60, |x, y| {
+            Rgb([(x * 2) as u8, (y * 4) as u8, ((x + y) * 255 / 180) as u8])
+        }));
+        let mut png = Vec::new();
+        input
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let mut outputs = Vec::new();
+        for dither in [false, true] {
+            let opts = SqframeOptions {
+                format: ImageFormat::Gif,
+                dither,
+                ..SqframeOptions::default()
+            };
+            let gif = square_bytes(&png, &opts).unwrap();
+            assert!(gif.starts_with(b"GIF8"));
+            assert_eq!(image::guess_format(&gif).unwrap(), ImageFormat::Gif);
+            let framed = image::load_from_memory_with_format(&gif, ImageFormat::Gif)
+                .unwrap()
+                .into_rgb8();
+            assert_eq!(framed.dimensions(), (120, 120));
+            let colors: HashSet<[u8; 3]> = framed.pixels().map(|pixel| pixel.0).collect();
+            assert!(colors.len() <= 256, "{} colors", colors.len());
+            // the foreground keeps the source's average color, whether or not its error is diffused
+            let foreground = framed.view(0, 30, 120, 60).to_image();
+            let mean = |image: &RgbImage| {
+                image
+                    .pixels()
+                    .map(|pixel| pixel.0.map(f32::from))
+                    .fold([0.; 3], |sum, p| {
+                        [sum[0] + p[0], sum[1] + p[1], sum[2] + p[2]]
+                    })
+                    .map(|total| total / image.pixels().len() as f32)
+            };
+            let (expected, actual) = (mean(&input.to_rgb8()), mean(&foreground));
+            for c in 0..3 {
+                assert!(
+                    (expected[c] - actual[c]).abs() < 4.,
+                    "{expected:?} vs {actual:?}"
+                );
+            }
+            outputs.push(gif);
+        }
+        assert_ne!(outputs[0], outputs[1], "dithering changed nothing");
+    }
+
+    #[test]
+    fn parallel_linear_blur_matches_a_serial_blur() {
+        let (width, height) = (37, 23);
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x * 7) as u8, (y * 11) as u8, ((x * y) % 256) as u8])
+        }));
+        let mut pixels = color::srgb_to_linear(&image.to_rgb8());
+        let (w, h) = (width as usize, height as usize);
+        let mut line = Vec::new();
+        for size in box_sizes(3.) {
+            let radius = (size - 1) / 2;
+            for y in 0..h {
+                box_blur_line(&mut pixels, y * w, 1, w, radius, &mut line);
+            }
+            for x in 0..w {
+                box_blur_line(&mut pixels, x, w, h, radius, &mut line);
+            }
+        }
+        let serial = color::linear_to_srgb(&pixels, width);
+        assert_eq!(blur_linear(&image, 3., 1).unwrap().into_rgb8(), serial);
+    }
+
+    #[test]
+    fn oversized_supersample_is_refused_before_resizing() {
+        // 4200² pixels are fine, but 16 times as many are beyond `MAX_BACKGROUND_PIXELS`
+        let image = DynamicImage::ImageLuma8(GrayImage::new(4200, 4200));
+        let opts = SqframeOptions {
+            supersample: 4,
+            ..SqframeOptions::default()
+        };
+        match square_image(&image, &opts) {
+            Err(SqframeError::Dimension(message)) => assert!(message.contains("supersample")),
+            other => panic!("expected a dimension error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn dithered_8_bit_output_keeps_the_16_bit_mean() {
+        // a quarter of the way from 100 to 101 in 8 bits
+        let value = 100 * 257 + 64;
+        let image = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(8, 8, Rgb([value; 3])));
+        let decode = |dither| {
+            let opts = SqframeOptions {
+                bit_depth: Some(BitDepth::Eight),
+                dither,
+                ..SqframeOptions::default()
+            };
+            let png = encode_image(&image, &opts).unwrap();
+            let decoded = image::load_from_memory(&png).unwrap();
+            assert_eq!(decoded.color(), image::ColorType::Rgb8);
+            decoded.into_rgb8()
+        };
+        let mean = |image: &RgbImage| {
+            image.pixels().map(|pixel| pixel.0[0] as f32).sum::<f32>() / image.pixels().len() as f32
+        };
+        let rounded = decode(false);
+        assert!(rounded.pixels().all(|pixel| pixel.0 == [100; 3]));
+        let dithered = decode(true);
+        assert!(dithered
+            .pixels()
+            .all(|pixel| matches!(pixel.0[0], 100 | 101)));
+        assert!((mean(&dithered) - value as f32 / 257.).abs() < 0.01);
+        // values an 8-bit image can hold exactly are left as they are
+        let exact = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(4, 4, Rgb([200 * 257; 3])));
+        assert!(dither_to_8_bit(&exact, false)
+            .into_rgb8()
+            .pixels()
+            .all(|pixel| pixel.0 == [200; 3]));
+    }
+
+    #[test]
+    fn extra_outputs_keep_only_supported_options() {
+        let opts = SqframeOptions {
+            format: ImageFormat::Png,
+            bit_depth: Some(BitDepth::Sixteen),
+            dpi: Some(300),
+            channels: Some(Channels::Rgba),
+            background: Background::None,
+            outer_radius: Some(20),
+            ..SqframeOptions::default()
+        };
+        opts.validate().unwrap();
+        for format in [
+            ImageFormat::Png,
+            ImageFormat::Jpeg,
+            ImageFormat::Bmp,
+            ImageFormat::Gif,
+            ImageFormat::Tiff,
+        ] {
+            let extra = opts.for_extra_output(format);
+            extra.validate().unwrap();
+            assert_eq!(extra.format, format);
+        }
+        assert_eq!(opts.for_extra_output(ImageFormat::Jpeg).dpi, Some(300));
+        assert_eq!(
+            opts.for_extra_output(ImageFormat::Tiff).channels,
+            Some(Channels::Rgba)
+        );
+    }
+
+    #[test]
+    fn blur_range_is_bounded() {
+        assert_eq!(parse_blur_range("8:40"), Ok((8., 40.)));
+        assert!(parse_blur_range("0:1e30").is_err());
+        assert!(parse_blur_range("-1:4").is_err());
+        assert!(parse_blur_range("4:NaN").is_err());
+        assert!(parse_blur_range("40:8").is_err());
+    }
 }
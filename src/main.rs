@@ -1,7 +1,20 @@
 use clap::Parser;
+use colored::Colorize;
 use sqframe::{run, Args};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    process,
+};
 
 fn main() {
+    // `colored` only checks whether stdout is a terminal, but errors go to stderr
+    if !io::stderr().is_terminal() && env::var_os("CLICOLOR_FORCE").is_none() {
+        colored::control::set_override(false);
+    }
     let args = Args::parse();
-    run(args);
+    if let Err(e) = run(args) {
+        eprintln!("ERROR: {}", e.to_string().bold().red());
+        process::exit(e.exit_code())
+    }
 }
@@ -0,0 +1,123 @@
+use std::{fmt::Write, path::Path};
+
+/// Outcome of one image of a batch, as listed in the manifest
+pub struct Entry {
+    /// Name of the image in the input
+    pub input: String,
+    /// Where the framed image was (or would have been) written
+    pub output: String,
+    /// Width and height of the framed image, if it was framed
+    pub dimensions: Option<(u32, u32)>,
+    /// What happened to the image
+    pub status: Status,
+}
+
+pub enum Status {
+    Framed,
+    Skipped(String),
+    Failed(String),
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Framed => "framed",
+            Status::Skipped(_) => "skipped",
+            Status::Failed(_) => "failed",
+        }
+    }
+
+    /// Returns why the image was skipped or failed, empty if it was framed
+    fn reason(&self) -> &str {
+        match self {
+            Status::Framed => "",
+            Status::Skipped(reason) | Status::Failed(reason) => reason,
+        }
+    }
+}
+
+/// Renders the manifest as JSON if `path` ends in `.json`, or as CSV otherwise
+pub fn render(path: &Path, entries: &[Entry]) -> String {
+    if path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+    {
+        to_json(entries)
+    } else {
+        to_csv(entries)
+    }
+}
+
+/// Renders one row per image under an `input,output,width,height,status,reason` header
+fn to_csv(entries: &[Entry]) -> String {
+    let mut csv = String::from("input,output,width,height,status,reason\n");
+    for entry in entries {
+        let (width, height) = entry
+            .dimensions
+            .map_or((String::new(), String::new()), |(width, height)| {
+                (width.to_string(), height.to_string())
+            });
+        let _ = writeln!(
+            csv,
+            "{},{},{width},{height},{},{}",
+            csv_field(&entry.input),
+            csv_field(&entry.output),
+            entry.status.name(),
+            csv_field(entry.status.reason()),
+        );
+    }
+    csv
+}
+
+/// Quotes a field if it contains a comma, quote or line break, doubling its quotes
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Renders an array of objects, with `null` dimensions and reason where they don't apply
+fn to_json(entries: &[Entry]) -> String {
+    let mut json = String::from("[");
+    for (i, entry) in entries.iter().enumerate() {
+        let (width, height) = entry.dimensions.map_or(
+            (String::from("null"), String::from("null")),
+            |(width, height)| (width.to_string(), height.to_string()),
+        );
+        let reason = match entry.status {
+            Status::Framed => String::from("null"),
+            _ => json_string(entry.status.reason()),
+        };
+        let _ = write!(
+            json,
+            "{}\n  {{\"input\": {}, \"output\": {}, \"width\": {width}, \"height\": {height}, \"status\": \"{}\", \"reason\": {reason}}}",
+            if i == 0 { "" } else { "," },
+            json_string(&entry.input),
+            json_string(&entry.output),
+            entry.status.name(),
+        );
+    }
+    json.push_str("\n]\n");
+    json
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,400 @@
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use image::ImageFormat;
+use std::io::{Read, Write};
+
+const JPEG_EXIF_HEADER: &[u8] = b"Exif\0\0";
+const JPEG_ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// EXIF tag holding the orientation (1–8) of the image
+const ORIENTATION_TAG: u16 = 0x0112;
+/// Maximum ICC payload per JPEG APP2 segment (65535 minus length, header, and sequence bytes)
+const JPEG_ICC_CHUNK: usize = 65519;
+const JPEG_JFIF_HEADER: &[u8] = b"JFIF\0";
+/// Length of a PNG chunk's length and type fields, which precede its data
+const PNG_CHUNK_HEADER: usize = 8;
+/// Meters per inch, for converting between dots per inch and PNG's pixels per meter
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// EXIF and ICC metadata carried over from the input file
+#[derive(Default, Debug)]
+pub struct Metadata {
+    /// Raw TIFF-structured EXIF data (without the JPEG `"Exif\0\0"` header)
+    pub exif: Option<Vec<u8>>,
+    /// Uncompressed ICC profile
+    pub icc: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.icc.is_none()
+    }
+}
+
+/// Extracts EXIF and ICC metadata from the bytes of a JPEG or PNG file, other formats yield no metadata
+pub fn read(bytes: &[u8]) -> Metadata {
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        read_jpeg(bytes)
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        read_png(bytes)
+    } else {
+        Metadata::default()
+    }
+}
+
+fn read_jpeg(bytes: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+    let mut icc_chunks: Vec<(u8, &[u8])> = Vec::new();
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        // start of scan: no more metadata segments follow
+        if marker == 0xDA {
+            break;
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let Some(data) = bytes.get(pos + 4..pos + 2 + len) else {
+            break;
+        };
+        if marker == 0xE1 && data.starts_with(JPEG_EXIF_HEADER) {
+            metadata.exif = Some(data[JPEG_EXIF_HEADER.len()..].to_vec());
+        } else if marker == 0xE2
+            && data.len() > JPEG_ICC_HEADER.len() + 2
+            && data.starts_with(JPEG_ICC_HEADER)
+        {
+            let seq = data[JPEG_ICC_HEADER.len()];
+            icc_chunks.push((seq, &data[JPEG_ICC_HEADER.len() + 2..]));
+        }
+        pos += 2 + len;
+    }
+    if !icc_chunks.is_empty() {
+        icc_chunks.sort_by_key(|(seq, _)| *seq);
+        metadata.icc = Some(
+            icc_chunks
+                .into_iter()
+                .flat_map(|(_, chunk)| chunk.to_vec())
+                .collect(),
+        );
+    }
+    metadata
+}
+
+fn read_png(bytes: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 8 <= bytes.len() {
+        let len = u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+            as usize;
+        let kind = &bytes[pos + 4..pos + 8];
+        let Some(data) = bytes.get(pos + 8..pos + 8 + len) else {
+            break;
+        };
+        match kind {
+            b"eXIf" => metadata.exif = Some(data.to_vec()),
+            b"iCCP" => {
+                // profile name, null separator, compression method, zlib stream
+                if let Some(null) = data.iter().position(|b| *b == 0) {
+                    let mut icc = Vec::new();
+                    if ZlibDecoder::new(data.get(null + 2..).unwrap_or_default())
+                        .read_to_end(&mut icc)
+                        .is_ok()
+                    {
+                        metadata.icc = Some(icc);
+                    }
+                }
+            }
+            b"IDAT" | b"IEND" => break,
+            _ => {}
+        }
+        pos += 12 + len;
+    }
+    metadata
+}
+
+/// Embeds `metadata` into an encoded image, returning `None` if the format cannot carry metadata
+pub fn embed(encoded: &[u8], format: ImageFormat, metadata: &Metadata) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => Some(embed_jpeg(encoded, metadata)),
+        ImageFormat::Png => Some(embed_png(encoded, metadata)),
+        _ => None,
+    }
+}
+
+/// Returns a JPEG marker segment holding `header` followed by `data`, or `None` if they are too long for the
+/// segment's 16-bit length
+fn jpeg_segment(marker: u8, header: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let len = u16::try_from(2 + header.len() + data.len()).ok()?;
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&len.to_be_bytes());
+    segment.extend_from_slice(header);
+    segment.extend_from_slice(data);
+    Some(segment)
+}
+
+fn embed_jpeg(encoded: &[u8], metadata: &Metadata) -> Vec<u8> {
+    let mut segments = Vec::new();
+    if let Some(exif) = &metadata.exif {
+        match jpeg_segment(0xE1, JPEG_EXIF_HEADER, exif) {
+            Some(segment) => segments.extend(segment),
+            None => eprintln!(
+                "WARNING: the EXIF data ({} bytes) is too large for a JPEG segment, saving without it",
+                exif.len()
+            ),
+        }
+    }
+    if let Some(icc) = &metadata.icc {
+        // the chunks are numbered with single bytes
+        match u8::try_from(icc.chunks(JPEG_ICC_CHUNK).count()) {
+            Ok(count) => {
+                for (i, chunk) in icc.chunks(JPEG_ICC_CHUNK).enumerate() {
+                    let mut header = JPEG_ICC_HEADER.to_vec();
+                    header.extend_from_slice(&[i as u8 + 1, count]);
+                    // `JPEG_ICC_CHUNK` is sized so that every chunk fits a segment
+                    if let Some(segment) = jpeg_segment(0xE2, &header, chunk) {
+                        segments.extend(segment);
+                    }
+                }
+            }
+            Err(_) => eprintln!(
+                "WARNING: the ICC profile ({} bytes) is too large for a JPEG, saving without it",
+                icc.len()
+            ),
+        }
+    }
+    // keep the JFIF APP0 segment (if any) directly after SOI
+    let mut insert_at = 2;
+    if encoded.get(2..4) == Some(&[0xFF, 0xE0]) {
+        insert_at += 2 + u16::from_be_bytes([encoded[4], encoded[5]]) as usize;
+    }
+    let mut output = encoded[..insert_at].to_vec();
+    output.extend(segments);
+    output.extend_from_slice(&encoded[insert_at..]);
+    output
+}
+
+fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    chunk.extend_from_slice(&hasher.finalize().to_be_bytes());
+    chunk
+}
+
+fn embed_png(encoded: &[u8], metadata: &Metadata) -> Vec<u8> {
+    let mut chunks = Vec::new();
+    if let Some(icc) = &metadata.icc {
+        let mut data = b"ICC profile\0\0".to_vec();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        if encoder.write_all(icc).is_ok() {
+            if let Ok(compressed) = encoder.finish() {
+                data.extend(compressed);
+                chunks.extend(png_chunk(b"iCCP", &data));
+            }
+        }
+    }
+    if let Some(exif) = &metadata.exif {
+        chunks.extend(png_chunk(b"eXIf", exif));
+    }
+    insert_png_chunks(encoded, &chunks)
+}
+
+/// Returns the encoded PNG with `chunks` inserted directly after its IHDR chunk
+fn insert_png_chunks(encoded: &[u8], chunks: &[u8]) -> Vec<u8> {
+    // IHDR is always the first chunk: signature (8) + length (4) + type (4) + data (13) + CRC (4)
+    let insert_at = PNG_SIGNATURE.len() + 25;
+    let mut output = encoded[..insert_at].to_vec();
+    output.extend_from_slice(chunks);
+    output.extend_from_slice(&encoded[insert_at..]);
+    output
+}
+
+/// Returns the encoded PNG with a `pHYs` chunk declaring a resolution of `dpi` dots per inch
+pub fn png_with_dpi(encoded: &[u8], dpi: u16) -> Vec<u8> {
+    let pixels_per_meter = (dpi as f64 / METERS_PER_INCH).round() as u32;
+    let mut data = pixels_per_meter.to_be_bytes().repeat(2);
+    // unit specifier 1: the meter
+    data.push(1);
+    insert_png_chunks(encoded, &png_chunk(b"pHYs", &data))
+}
+
+/// Returns the horizontal and vertical resolution, in dots per inch, declared by the JFIF header of a JPEG
+/// file or the `pHYs` chunk of a PNG file; `None` for other formats or if it only gives an aspect ratio
+pub fn resolution(bytes: &[u8]) -> Option<(f64, f64)> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF, 0xE0]) {
+        let data = bytes.get(6..)?.strip_prefix(JPEG_JFIF_HEADER)?;
+        let (x, y) = (
+            u16::from_be_bytes([*data.get(3)?, *data.get(4)?]) as f64,
+            u16::from_be_bytes([*data.get(5)?, *data.get(6)?]) as f64,
+        );
+        return match data.get(2)? {
+            1 => Some((x, y)),
+            2 => Some((x * 2.54, y * 2.54)),
+            _ => None,
+        };
+    }
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+    let mut pos = PNG_SIGNATURE.len();
+    while let Some(header) = bytes.get(pos..pos + PNG_CHUNK_HEADER) {
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let data = bytes.get(pos + PNG_CHUNK_HEADER..pos + PNG_CHUNK_HEADER + len)?;
+        match &header[4..] {
+            b"pHYs" if data.len() == 9 && data[8] == 1 => {
+                let x = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f64;
+                let y = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as f64;
+                return Some((x * METERS_PER_INCH, y * METERS_PER_INCH));
+            }
+            b"IDAT" | b"IEND" => return None,
+            _ => {}
+        }
+        pos += PNG_CHUNK_HEADER + len + 4;
+    }
+    None
+}
+
+/// Returns whether TIFF-structured EXIF data is big-endian, or `None` if its byte order mark is invalid
+fn big_endian(exif: &[u8]) -> Option<bool> {
+    match exif.get(..2)? {
+        b"MM" => Some(true),
+        b"II" => Some(false),
+        _ => None,
+    }
+}
+
+fn u16_at(data: &[u8], pos: usize, big_endian: bool) -> Option<u16> {
+    let bytes = [*data.get(pos)?, *data.get(pos + 1)?];
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn u32_at(data: &[u8], pos: usize, big_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(pos..pos + 4)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+fn u16_bytes(value: u16, big_endian: bool) -> [u8; 2] {
+    if big_endian {
+        value.to_be_bytes()
+    } else {
+        value.to_le_bytes()
+    }
+}
+
+fn u32_bytes(value: u32, big_endian: bool) -> [u8; 4] {
+    if big_endian {
+        value.to_be_bytes()
+    } else {
+        value.to_le_bytes()
+    }
+}
+
+/// Returns an IFD entry setting the orientation tag to `value`
+fn orientation_entry(value: u16, big_endian: bool) -> Vec<u8> {
+    let mut entry = u16_bytes(ORIENTATION_TAG, big_endian).to_vec();
+    // type SHORT, a single value, stored left-aligned in the 4-byte value field
+    entry.extend(u16_bytes(3, big_endian));
+    entry.extend(u32_bytes(1, big_endian));
+    entry.extend(u16_bytes(value, big_endian));
+    entry.extend([0, 0]);
+    entry
+}
+
+/// Returns the value of the orientation tag (1–8) in the first IFD of raw EXIF data, if present
+pub fn orientation(exif: &[u8]) -> Option<u16> {
+    let big_endian = big_endian(exif)?;
+    let ifd = u32_at(exif, 4, big_endian)? as usize;
+    let entries = u16_at(exif, ifd, big_endian)? as usize;
+    (0..entries)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|entry| u16_at(exif, *entry, big_endian) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(exif, entry + 8, big_endian))
+}
+
+/// Returns raw EXIF data with the orientation tag of its first IFD set to `value`, keeping the rest of `exif`;
+/// without (valid) `exif`, the result holds just the orientation
+pub fn set_orientation(exif: Option<&[u8]>, value: u16) -> Vec<u8> {
+    let parsed = exif.and_then(|exif| {
+        let big_endian = big_endian(exif)?;
+        let ifd = u32_at(exif, 4, big_endian)? as usize;
+        let count = u16_at(exif, ifd, big_endian)? as usize;
+        // the entries and the offset of the next IFD
+        exif.get(ifd..ifd + 2 + count * 12 + 4)?;
+        Some((exif, big_endian, ifd, count))
+    });
+    let Some((exif, big_endian, ifd, count)) = parsed else {
+        let mut data = b"MM\0\x2a".to_vec();
+        data.extend(8u32.to_be_bytes());
+        data.extend(1u16.to_be_bytes());
+        data.extend(orientation_entry(value, true));
+        data.extend(0u32.to_be_bytes());
+        return data;
+    };
+    let entries: Vec<&[u8]> = (0..count)
+        .map(|i| &exif[ifd + 2 + i * 12..ifd + 14 + i * 12])
+        .collect();
+    let tags: Vec<Option<u16>> = entries
+        .iter()
+        .map(|entry| u16_at(entry, 0, big_endian))
+        .collect();
+    let mut data = exif.to_vec();
+    if let Some(i) = tags.iter().position(|tag| *tag == Some(ORIENTATION_TAG)) {
+        let pos = ifd + 2 + i * 12;
+        data[pos..pos + 12].copy_from_slice(&orientation_entry(value, big_endian));
+        return data;
+    }
+    // adding an entry in place would shift the data after the IFD, which other offsets point into, so the IFD
+    // is copied (with the entry added, keeping tags in ascending order) to the end instead
+    if data.len() % 2 == 1 {
+        data.push(0);
+    }
+    let new_ifd = data.len() as u32;
+    let insert_at = tags
+        .iter()
+        .position(|tag| tag.is_some_and(|tag| tag > ORIENTATION_TAG))
+        .unwrap_or(count);
+    data.extend(u16_bytes(count as u16 + 1, big_endian));
+    for (i, entry) in entries.iter().enumerate() {
+        if i == insert_at {
+            data.extend(orientation_entry(value, big_endian));
+        }
+        data.extend_from_slice(entry);
+    }
+    if insert_at == count {
+        data.extend(orientation_entry(value, big_endian));
+    }
+    data.extend_from_slice(&exif[ifd + 2 + count * 12..ifd + 6 + count * 12]);
+    data[4..8].copy_from_slice(&u32_bytes(new_ifd, big_endian));
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_exif_is_dropped_from_jpeg() {
+        // SOI, then EOI
+        let encoded = [0xFF, 0xD8, 0xFF, 0xD9];
+        let fits = Metadata {
+            exif: Some(vec![0; u16::MAX as usize - 2 - JPEG_EXIF_HEADER.len()]),
+            ..Metadata::default()
+        };
+        let embedded = embed_jpeg(&encoded, &fits);
+        assert_eq!(embedded[2..6], [0xFF, 0xE1, 0xFF, 0xFF]);
+        let too_large = Metadata {
+            exif: Some(vec![0; u16::MAX as usize]),
+            ..Metadata::default()
+        };
+        assert_eq!(embed_jpeg(&encoded, &too_large), encoded);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{square_bytes, SqframeError, SqframeOptions};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Path of the only endpoint, which frames the image POSTed to it
+const ENDPOINT: &str = "/frame";
+/// Largest request body accepted, so that a client cannot make the server buffer arbitrarily much
+const MAX_BODY: usize = 64 * 1024 * 1024;
+/// Largest request line or header accepted
+const MAX_LINE: usize = 8 * 1024;
+/// Number of requests answered at once, each framing an image in memory
+const WORKERS: usize = 4;
+/// Number of connections waiting for a worker before further ones are turned away
+const MAX_QUEUED: usize = 16;
+/// How long a read from or write to a client may stall before its connection is dropped, so that idle
+/// clients cannot hold on to workers
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Serves `POST /frame` on `port` of the loopback interface until interrupted, answering each request (on one
+/// of `WORKERS` threads) with the image in its body framed with `opts`, in the image's own format
+pub fn serve(port: u16, opts: SqframeOptions) -> Result<(), SqframeError> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .map_err(SqframeError::io(format!("could not listen on port {port}")))?;
+    println!(
+        "Serving POST http://{}{ENDPOINT}",
+        listener
+            .local_addr()
+            .map_or_else(|_| format!("localhost:{port}"), |addr| addr.to_string())
+    );
+    let opts = Arc::new(opts);
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(MAX_QUEUED);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..WORKERS {
+        let (receiver, opts) = (Arc::clone(&receiver), Arc::clone(&opts));
+        thread::spawn(move || loop {
+            // the lock is only held while waiting for a connection, not while answering it
+            let stream = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => return,
+            };
+            let Ok(stream) = stream else {
+                return;
+            };
+            // a panic while reading or writing must not take the worker down with the connection
+            match panic::catch_unwind(AssertUnwindSafe(|| handle(stream, &opts))) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => eprintln!("WARNING: could not answer a request: {e}"),
+                Err(_) => eprintln!("WARNING: answering a request panicked"),
+            }
+        });
+    }
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("WARNING: could not accept a connection: {e}");
+                continue;
+            }
+        };
+        match sender.try_send(stream) {
+            Ok(()) => {}
+            Err(TrySendError::Full(stream)) => {
+                let busy =
+                    Response::text("503 Service Unavailable", "too many requests, retry later");
+                if let Err(e) = set_timeouts(&stream).and_then(|_| respond(stream, busy)) {
+                    eprintln!("WARNING: could not turn a request away: {e}");
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                return Err(SqframeError::io("could not hand a connection to a worker")(
+                    io::Error::other("all workers have stopped"),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Makes reads from and writes to `stream` fail after stalling for `IO_TIMEOUT`
+fn set_timeouts(stream: &TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))
+}
+
+/// Reads one request from `stream` and writes the response, closing the connection afterwards
+fn handle(stream: TcpStream, opts: &SqframeOptions) -> io::Result<()> {
+    set_timeouts(&stream)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        // `image` can panic on malformed input, which is answered like any other failure
+        Ok(body) => panic::catch_unwind(|| frame(&body, opts)).unwrap_or_else(|_| {
+            Response::text("500 Internal Server Error", "framing the image panicked")
+        }),
+        Err(response) => response,
+    };
+    respond(stream, response)
+}
+
+/// Writes `response` to `stream`, closing the connection afterwards
+fn respond(mut stream: TcpStream, response: Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: &'static str, message: impl Into<String>) -> Response {
+        let mut body = message.into().into_bytes();
+        body.push(b'\n');
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+}
+
+/// Reads the request line, headers and body, returning the body of a `POST /frame` or the error response
+/// for anything else
+fn read_request(reader: &mut impl BufRead) -> Result<Vec<u8>, Response> {
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    );
+    let mut content_length = None;
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(Response::text("400 Bad Request", "malformed header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| Response::text("400 Bad Request", "invalid Content-Length"))?,
+            );
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(Response::text(
+                "411 Length Required",
+                "chunked bodies are not supported, send a Content-Length",
+            ));
+        }
+    }
+    if path.split('?').next() != Some(ENDPOINT) {
+        return Err(Response::text(
+            "404 Not Found",
+            format!("the only endpoint is POST {ENDPOINT}"),
+        ));
+    }
+    if method != "POST" {
+        return Err(Response::text(
+            "405 Method Not Allowed",
+            format!("{ENDPOINT} only accepts POST"),
+        ));
+    }
+    let content_length = content_length
+        .ok_or_else(|| Response::text("411 Length Required", "a Content-Length is required"))?;
+    if content_length > MAX_BODY {
+        return Err(Response::text(
+            "413 Payload Too Large",
+            format!("the image must not be larger than {MAX_BODY} bytes"),
+        ));
+    }
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|e| read_error("could not read the body", e))?;
+    Ok(body)
+}
+
+/// Reads a CRLF- (or LF-) terminated line of at most `MAX_LINE` bytes, without its terminator
+fn read_line(reader: &mut impl BufRead) -> Result<String, Response> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .map_err(|e| read_error("could not read the request", e))?;
+    if line.len() > MAX_LINE {
+        return Err(Response::text(
+            "431 Request Header Fields Too Large",
+            "request line or header too long",
+        ));
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(Response::text("400 Bad Request", "incomplete request"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| Response::text("400 Bad Request", "request is not UTF-8"))
+}
+
+/// Returns the response to a request that could not be read, a timeout if the client stalled for `IO_TIMEOUT`
+fn read_error(context: &str, e: io::Error) -> Response {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            Response::text("408 Request Timeout", format!("{context}: timed out"))
+        }
+        _ => Response::text("400 Bad Request", format!("{context}: {e}")),
+    }
+}
+
+/// Frames the image in `body`, keeping its format
+fn frame(body: &[u8], opts: &SqframeOptions) -> Response {
+    let format = match image::guess_format(body) {
+        Ok(format) => format,
+        Err(e) => return Response::text("400 Bad Request", format!("could not decode image: {e}")),
+    };
+    match square_bytes(body, &opts.with_format(format)) {
+        Ok(body) => Response {
+            status: "200 OK",
+            content_type: format.to_mime_type(),
+            body,
+        },
+        Err(e @ (SqframeError::InvalidArgs(_) | SqframeError::Decode(_))) => {
+            Response::text("400 Bad Request", e.to_string())
+        }
+        Err(e @ (SqframeError::Dimension(_) | SqframeError::LowContrast(_))) => {
+            Response::text("422 Unprocessable Content", e.to_string())
+        }
+        Err(e) => Response::text("500 Internal Server Error", e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    #[test]
+    fn frame_answers_bad_images_with_400() {
+        let opts = SqframeOptions::default();
+        assert_eq!(frame(b"not an image", &opts).status, "400 Bad Request");
+        assert_eq!(frame(b"GIF89a garbage", &opts).status, "400 Bad Request");
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(4, 2))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let framed = frame(&png, &opts);
+        assert_eq!(framed.status, "200 OK");
+        assert_eq!(framed.content_type, "image/png");
+    }
+}
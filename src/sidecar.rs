@@ -0,0 +1,55 @@
+use crate::check_blur_radius;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Extension appended to an image's file name to name its sidecar, e.g. `photo.jpg.sqframe`
+pub const EXTENSION: &str = "sqframe";
+
+/// Per-image settings read from a sidecar file next to an image of a batch, overriding the command line's
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Sidecar {
+    /// Blur radius of the background fill
+    pub blur: Option<f32>,
+    /// Settings this version does not know, which are skipped with a warning
+    #[serde(flatten)]
+    unknown: BTreeMap<String, Value>,
+}
+
+/// Parses a sidecar from a JSON object whose members are settings, e.g. `{"blur": 24}`. Unknown settings, whatever
+/// their values, are skipped with a warning, so that a sidecar written for a newer version still applies the
+/// settings this one knows
+pub fn parse(text: &str) -> Result<Sidecar, String> {
+    let sidecar: Sidecar = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    for key in sidecar.unknown.keys() {
+        eprintln!("WARNING: ignoring unknown sidecar setting {key:?}");
+    }
+    if let Some(blur) = sidecar.blur {
+        check_blur_radius(blur)?;
+    }
+    Ok(sidecar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_settings_of_any_type_are_skipped() {
+        let sidecar =
+            parse(r#"{"note": "x", "ratio": "16:9", "nested": {"a": [1, null]}, "blur": 24}"#)
+                .unwrap();
+        assert_eq!(sidecar.blur, Some(24.));
+        assert_eq!(sidecar.unknown.len(), 3);
+        assert_eq!(parse(r#"{"note": "x"}"#).unwrap().blur, None);
+    }
+
+    #[test]
+    fn invalid_blur_is_an_error() {
+        assert!(parse(r#"{"blur": -1}"#).is_err());
+        assert!(parse(r#"{"blur": "24"}"#).is_err());
+        assert!(parse(r#"{"blur": 1e9}"#).is_err());
+        assert!(parse("[24]").is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use crate::SqframeError;
+use image::{DynamicImage, ImageFormat};
+use std::{
+    io,
+    process::{Command, Stdio},
+};
+
+/// Decodes the frame of the video at `path` that is shown `seconds` in, by piping it out of `ffmpeg` as a PNG
+pub fn frame_at(path: &str, seconds: f64) -> Result<DynamicImage, SqframeError> {
+    let output = Command::new("ffmpeg")
+        .args([
+            "-nostdin",
+            "-v",
+            "error",
+            "-ss",
+            &seconds.to_string(),
+            "-i",
+            path,
+        ])
+        .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "png", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(SqframeError::io("could not run ffmpeg"))?;
+    if !output.status.success() || output.stdout.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = match stderr.trim() {
+            "" => format!("the video has no frame at {seconds}s"),
+            stderr => stderr.to_string(),
+        };
+        return Err(SqframeError::Io {
+            context: format!("could not extract a frame from {path:?}"),
+            source: io::Error::other(reason),
+        });
+    }
+    let image = image::load_from_memory_with_format(&output.stdout, ImageFormat::Png)
+        .map_err(SqframeError::Decode)?;
+    progress!(
+        "Extracted frame at {seconds}s ({}x{})",
+        image.width(),
+        image.height()
+    );
+    Ok(image)
+}